@@ -0,0 +1,226 @@
+//! Static libraries, like `ar` and `lib.exe` write them, to hold compiled resources
+//!
+//! Both flavours are `!<arch>\n` followed by members, each with a 60-byte text header and aligned to 2 bytes.
+//! GNU archives start with a `/` symbol table (big-endian), then `//` holds names longer than 15 bytes, each terminated by `/\n`.
+//! MSVC ones have a second `/` member after the first, with the symbols sorted and little-endian member indices,
+//! and NUL-terminate the long names.
+//!
+//! Timestamps, UIDs, and GIDs are always 0, so the same members always make the same archive.
+//!
+//! https://learn.microsoft.com/en-us/windows/win32/debug/pe-format#archive-library-file-format
+
+
+/// Whose format to write
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Flavour {
+    /// `ar`, for MinGW and everything that isn't MSVC
+    Gnu,
+    /// `lib.exe`
+    Msvc,
+}
+
+impl Flavour {
+    /// The archive flavour the linker for a Rust target triple expects
+    pub fn from_target(target: &str) -> Flavour {
+        if target.ends_with("-msvc") {
+            Flavour::Msvc
+        } else {
+            Flavour::Gnu
+        }
+    }
+}
+
+/// A file to put in an archive, and the external symbols it defines
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Member<'d> {
+    pub name: String,
+    pub data: &'d [u8],
+    pub symbols: Vec<String>,
+}
+
+
+/// Write an archive of `members`, in order
+pub fn write(members: &[Member], flavour: Flavour) -> Vec<u8> {
+    let mut long_names = vec![];
+    let names = members.iter()
+        .map(|m| if m.name.len() <= 15 {
+            format!("{}/", m.name)
+        } else {
+            let ret = format!("/{}", long_names.len());
+            long_names.extend_from_slice(m.name.as_bytes());
+            long_names.extend_from_slice(if flavour == Flavour::Gnu { b"/\n" } else { b"\0" });
+            ret
+        })
+        .collect::<Vec<_>>();
+
+    let symbols = members.iter().enumerate().flat_map(|(i, m)| m.symbols.iter().map(move |s| (s, i))).collect::<Vec<_>>();
+    let strings_len = symbols.iter().map(|(s, _)| s.len() + 1).sum::<usize>();
+    let first_linker_len = 4 + 4 * symbols.len() + strings_len;
+    let second_linker_len = 4 + 4 * members.len() + 4 + 2 * symbols.len() + strings_len;
+
+    let mut offset = 8 + 60 + align(first_linker_len);
+    if flavour == Flavour::Msvc {
+        offset += 60 + align(second_linker_len);
+    }
+    if !long_names.is_empty() {
+        offset += 60 + align(long_names.len());
+    }
+    let offsets = members.iter()
+        .map(|m| {
+            let ret = offset as u32;
+            offset += 60 + align(m.data.len());
+            ret
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = b"!<arch>\n".to_vec();
+    write_header(&mut out, "/", first_linker_len, "0");
+    out.extend_from_slice(&(symbols.len() as u32).to_be_bytes());
+    for (_, i) in &symbols {
+        out.extend_from_slice(&offsets[*i].to_be_bytes());
+    }
+    write_strings(&mut out, symbols.iter().map(|(s, _)| *s));
+    pad(&mut out);
+
+    if flavour == Flavour::Msvc {
+        let mut sorted = symbols.clone();
+        sorted.sort();
+        write_header(&mut out, "/", second_linker_len, "0");
+        out.extend_from_slice(&(members.len() as u32).to_le_bytes());
+        for offset in &offsets {
+            out.extend_from_slice(&offset.to_le_bytes());
+        }
+        out.extend_from_slice(&(sorted.len() as u32).to_le_bytes());
+        for (_, i) in &sorted {
+            out.extend_from_slice(&(*i as u16 + 1).to_le_bytes());
+        }
+        write_strings(&mut out, sorted.iter().map(|(s, _)| *s));
+        pad(&mut out);
+    }
+
+    if !long_names.is_empty() {
+        write_header(&mut out, "//", long_names.len(), "");
+        out.extend_from_slice(&long_names);
+        pad(&mut out);
+    }
+
+    for (m, name) in members.iter().zip(names) {
+        write_header(&mut out, &name, m.data.len(), "644");
+        out.extend_from_slice(m.data);
+        pad(&mut out);
+    }
+    out
+}
+
+/// Name, date, UID, GID, mode (octal), size, and the terminator; blank long-names headers only have the name and size
+fn write_header(out: &mut Vec<u8>, name: &str, size: usize, mode: &str) {
+    let zero = if name == "//" { "" } else { "0" };
+    out.extend_from_slice(format!("{:16}{:12}{:6}{:6}{:8}{:<10}`\n", name, zero, zero, zero, mode, size).as_bytes());
+}
+
+fn write_strings<'s, S: Iterator<Item = &'s String>>(out: &mut Vec<u8>, strings: S) {
+    for s in strings {
+        out.extend_from_slice(s.as_bytes());
+        out.push(0);
+    }
+}
+
+fn align(n: usize) -> usize {
+    (n + 1) & !1
+}
+
+fn pad(out: &mut Vec<u8>) {
+    if out.len() % 2 != 0 {
+        out.push(b'\n');
+    }
+}
+
+
+/// `ar t` and the symbols in the first linker member
+#[cfg(test)]
+#[allow(clippy::type_complexity)]
+fn read(data: &[u8], flavour: Flavour) -> (Vec<(String, Vec<u8>)>, Vec<(String, usize)>) {
+    assert_eq!(&data[..8], b"!<arch>\n");
+    let mut headers = vec![];
+    let mut cur = 8;
+    while cur < data.len() {
+        let header = std::str::from_utf8(&data[cur..cur + 60]).unwrap();
+        assert_eq!(&header[58..], "`\n");
+        // Left-aligned, padded with spaces, like llvm-ar insists
+        let size = header[48..58].trim_end().parse::<usize>().unwrap();
+        headers.push((cur, header[..16].trim_end().to_string(), &data[cur + 60..cur + 60 + size]));
+        cur = align(cur + 60 + size);
+    }
+
+    let linker_members = if flavour == Flavour::Msvc { 2 } else { 1 };
+    assert!(headers[..linker_members].iter().all(|(_, name, _)| name == "/"));
+    let (long_names, members) = match headers.get(linker_members) {
+        Some((_, name, data)) if name == "//" => (*data, &headers[linker_members + 1..]),
+        _ => (&[][..], &headers[linker_members..]),
+    };
+
+    let first_linker = headers[0].2;
+    let count = u32::from_be_bytes(first_linker[..4].try_into().unwrap()) as usize;
+    let mut strings = first_linker[4 + 4 * count..].split(|&b| b == 0).map(|s| String::from_utf8(s.to_vec()).unwrap());
+    let symbols = (0..count)
+        .map(|i| {
+            let offset = u32::from_be_bytes(first_linker[4 + 4 * i..8 + 4 * i].try_into().unwrap()) as usize;
+            (strings.next().unwrap(), members.iter().position(|(at, _, _)| *at == offset).unwrap())
+        })
+        .collect::<Vec<_>>();
+
+    let members = members.iter()
+        .map(|(_, name, data)| {
+            let name = match name.strip_prefix('/') {
+                Some(offset) => {
+                    let long = &long_names[offset.parse::<usize>().unwrap()..];
+                    let end = long.iter().position(|&b| b == b'\0' || b == b'/').unwrap();
+                    String::from_utf8(long[..end].to_vec()).unwrap()
+                }
+                None => name.strip_suffix('/').unwrap().to_string(),
+            };
+            (name, data.to_vec())
+        })
+        .collect::<Vec<_>>();
+    (members, symbols)
+}
+
+#[test]
+fn round_trip() {
+    let members = [Member {
+                       name: "t.o".to_string(),
+                       data: b"odd",
+                       symbols: vec![],
+                   },
+                   Member {
+                       name: "a-much-longer-member-name.obj".to_string(),
+                       data: b"even",
+                       symbols: vec!["zzz".to_string(), "aaa".to_string()],
+                   },
+                   Member {
+                       name: "exactly-15-byte".to_string(),
+                       data: b"",
+                       symbols: vec!["mmm".to_string()],
+                   }];
+    for flavour in [Flavour::Gnu, Flavour::Msvc] {
+        let (read_members, symbols) = read(&write(&members, flavour), flavour);
+        assert_eq!(read_members, members.iter().map(|m| (m.name.clone(), m.data.to_vec())).collect::<Vec<_>>());
+        assert_eq!(symbols, [("zzz".to_string(), 1), ("aaa".to_string(), 1), ("mmm".to_string(), 2)]);
+    }
+}
+
+#[test]
+fn msvc_second_linker_member() {
+    let archive = write(&[Member {
+                              name: "t.obj".to_string(),
+                              data: b"t",
+                              symbols: vec!["b".to_string(), "a".to_string()],
+                          }],
+                        Flavour::Msvc);
+    // Past the signature, the first linker member's header and its 4 + 2*4 + 2*2 bytes
+    let second = &archive[8 + 60 + 16 + 60..];
+    assert_eq!(&second[..4], 1u32.to_le_bytes());
+    assert_eq!(&second[8..12], 2u32.to_le_bytes());
+    assert_eq!(&second[12..16], [1, 0, 1, 0]);
+    assert_eq!(&second[16..20], b"a\0b\0");
+}
@@ -0,0 +1,1526 @@
+//! A resource compiler that needs no external tools
+//!
+//! This implements a subset of the C preprocessor
+//! (`#include`, `#define`, `#undef`, `#if`, `#ifdef`, `#ifndef`, `#elif`, `#else`, `#endif`),
+//! and the `LANGUAGE`, `STRINGTABLE`, `VERSIONINFO`, `ICON`, `BITMAP`, `RCDATA`, `HTML`, `MESSAGETABLE`,
+//! and user-defined (like `1 RT_MANIFEST "app.exe.manifest"`) resource statements,
+//! which is what the overwhelming majority of resource scripts attached to Rust programs use.
+//!
+//! The output matches `llvm-rc`'s.
+//!
+//! Windows SDK headers (`<windows.h>`, `<winres.h>`, &c.) aren't available, so including them is a no-op,
+//! and the constants usually used from them in resource scripts are predefined instead.
+
+
+use self::super::res::{ResEntry, ResId, MEMORY_MOVEABLE, MEMORY_PURE, MEMORY_PRELOAD, MEMORY_DISCARDABLE, pad4};
+use std::collections::HashMap;
+use std::path::{PathBuf, Path};
+use std::borrow::Cow;
+use std::rc::Rc;
+use std::fs;
+
+
+const RT_BITMAP: u16 = 2;
+const RT_ICON: u16 = 3;
+const RT_STRING: u16 = 6;
+const RT_RCDATA: u16 = 10;
+const RT_MESSAGETABLE: u16 = 11;
+const RT_GROUP_ICON: u16 = 14;
+const RT_VERSION: u16 = 16;
+const RT_HTML: u16 = 23;
+
+/// `LANG_ENGLISH`, `SUBLANG_ENGLISH_US`
+const DEFAULT_LANGUAGE: u16 = 0x0409;
+
+/// Included from `<windows.h>` and friends in resource scripts
+const SYSTEM_HEADERS: &[&str] = &["windows.h", "winres.h", "winresrc.h", "winver.h", "verrsrc.h", "winuser.h", "winuser.rh", "winnt.h", "winnt.rh",
+                                  "windef.h", "winbase.h", "commctrl.h", "dde.rh", "afxres.h", "sdkddkver.h", "ntverp.h", "common.ver"];
+
+/// The part of the aforementioned headers that's useful without `DIALOG`s &c.
+const SYSTEM_DEFINES: &[&str] = &["RC_INVOKED 1",
+                                  "_WIN32 1",
+                                  "MAKELANGID(p, s) ((s) * 1024 + (p))",
+                                  "MAKEINTRESOURCE(i) i",
+                                  "RT_CURSOR 1",
+                                  "RT_BITMAP 2",
+                                  "RT_ICON 3",
+                                  "RT_MENU 4",
+                                  "RT_DIALOG 5",
+                                  "RT_STRING 6",
+                                  "RT_FONTDIR 7",
+                                  "RT_FONT 8",
+                                  "RT_ACCELERATOR 9",
+                                  "RT_RCDATA 10",
+                                  "RT_MESSAGETABLE 11",
+                                  "RT_GROUP_CURSOR 12",
+                                  "RT_GROUP_ICON 14",
+                                  "RT_VERSION 16",
+                                  "RT_DLGINCLUDE 17",
+                                  "RT_PLUGPLAY 19",
+                                  "RT_VXD 20",
+                                  "RT_ANICURSOR 21",
+                                  "RT_ANIICON 22",
+                                  "RT_HTML 23",
+                                  "RT_MANIFEST 24",
+                                  "CREATEPROCESS_MANIFEST_RESOURCE_ID 1",
+                                  "ISOLATIONAWARE_MANIFEST_RESOURCE_ID 2",
+                                  "ISOLATIONAWARE_NOSTATICIMPORT_MANIFEST_RESOURCE_ID 3",
+                                  "VS_VERSION_INFO 1",
+                                  "VS_USER_DEFINED 100",
+                                  "VS_FFI_SIGNATURE 0xFEEF04BDL",
+                                  "VS_FFI_STRUCVERSION 0x00010000L",
+                                  "VS_FFI_FILEFLAGSMASK 0x0000003FL",
+                                  "VS_FF_DEBUG 0x00000001L",
+                                  "VS_FF_PRERELEASE 0x00000002L",
+                                  "VS_FF_PATCHED 0x00000004L",
+                                  "VS_FF_PRIVATEBUILD 0x00000008L",
+                                  "VS_FF_INFOINFERRED 0x00000010L",
+                                  "VS_FF_SPECIALBUILD 0x00000020L",
+                                  "VOS_UNKNOWN 0x00000000L",
+                                  "VOS_DOS 0x00010000L",
+                                  "VOS_OS216 0x00020000L",
+                                  "VOS_OS232 0x00030000L",
+                                  "VOS_NT 0x00040000L",
+                                  "VOS_WINCE 0x00050000L",
+                                  "VOS__BASE 0x00000000L",
+                                  "VOS__WINDOWS16 0x00000001L",
+                                  "VOS__PM16 0x00000002L",
+                                  "VOS__PM32 0x00000003L",
+                                  "VOS__WINDOWS32 0x00000004L",
+                                  "VOS_DOS_WINDOWS16 0x00010001L",
+                                  "VOS_DOS_WINDOWS32 0x00010004L",
+                                  "VOS_OS216_PM16 0x00020002L",
+                                  "VOS_OS232_PM32 0x00030003L",
+                                  "VOS_NT_WINDOWS32 0x00040004L",
+                                  "VFT_UNKNOWN 0x00000000L",
+                                  "VFT_APP 0x00000001L",
+                                  "VFT_DLL 0x00000002L",
+                                  "VFT_DRV 0x00000003L",
+                                  "VFT_FONT 0x00000004L",
+                                  "VFT_VXD 0x00000005L",
+                                  "VFT_STATIC_LIB 0x00000007L",
+                                  "VFT2_UNKNOWN 0x00000000L",
+                                  "LANG_NEUTRAL 0x00",
+                                  "LANG_INVARIANT 0x7f",
+                                  "LANG_ARABIC 0x01",
+                                  "LANG_CHINESE 0x04",
+                                  "LANG_CZECH 0x05",
+                                  "LANG_DANISH 0x06",
+                                  "LANG_GERMAN 0x07",
+                                  "LANG_GREEK 0x08",
+                                  "LANG_ENGLISH 0x09",
+                                  "LANG_SPANISH 0x0a",
+                                  "LANG_FINNISH 0x0b",
+                                  "LANG_FRENCH 0x0c",
+                                  "LANG_HEBREW 0x0d",
+                                  "LANG_HUNGARIAN 0x0e",
+                                  "LANG_ITALIAN 0x10",
+                                  "LANG_JAPANESE 0x11",
+                                  "LANG_KOREAN 0x12",
+                                  "LANG_DUTCH 0x13",
+                                  "LANG_NORWEGIAN 0x14",
+                                  "LANG_POLISH 0x15",
+                                  "LANG_PORTUGUESE 0x16",
+                                  "LANG_ROMANIAN 0x18",
+                                  "LANG_RUSSIAN 0x19",
+                                  "LANG_SWEDISH 0x1d",
+                                  "LANG_TURKISH 0x1f",
+                                  "LANG_UKRAINIAN 0x22",
+                                  "SUBLANG_NEUTRAL 0x00",
+                                  "SUBLANG_DEFAULT 0x01",
+                                  "SUBLANG_SYS_DEFAULT 0x02",
+                                  "SUBLANG_ENGLISH_US 0x01",
+                                  "SUBLANG_ENGLISH_UK 0x02",
+                                  "SUBLANG_CHINESE_TRADITIONAL 0x01",
+                                  "SUBLANG_CHINESE_SIMPLIFIED 0x02",
+                                  "SUBLANG_GERMAN 0x01",
+                                  "SUBLANG_FRENCH 0x01",
+                                  "SUBLANG_SPANISH 0x01",
+                                  "SUBLANG_PORTUGUESE 0x02",
+                                  "SUBLANG_PORTUGUESE_BRAZILIAN 0x01",
+                                  "SUBLANG_POLISH_POLAND 0x01",
+                                  "SUBLANG_RUSSIAN_RUSSIA 0x01",
+                                  "SUBLANG_JAPANESE_JAPAN 0x01",
+                                  "SUBLANG_KOREAN 0x01"];
+
+
+/// Preprocess and compile `resource` into resource entries, in `.res` order, and every file read to do so
+///
+/// Resources referenced by filename are looked up next to the file referencing them, next to `resource`, in `include_dirs`,
+/// then in the current directory.
+pub fn compile<'m, M: IntoIterator<Item = &'m str>>(resource: &Path, macros: M, include_dirs: Vec<PathBuf>)
+                                                    -> Result<(Vec<ResEntry>, Vec<PathBuf>), Cow<'static, str>> {
+    let mut pp = Preprocessor {
+        macros: HashMap::new(),
+        include_dirs,
+        once: vec![],
+        lines: vec![],
+        depth: 0,
+        deps: vec![],
+    };
+    for def in SYSTEM_DEFINES {
+        pp.define(def);
+    }
+    for m in macros {
+        let mut m = m.splitn(2, '=');
+        let name = m.next().unwrap();
+        pp.define(&format!("{} {}", name, m.next().unwrap_or("1")));
+    }
+    pp.file(resource)?;
+
+    let tokens = lex(&pp.lines)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        lines: &pp.lines,
+        resource_dir: resource.parent().unwrap_or(Path::new("")),
+        include_dirs: &pp.include_dirs,
+        language: DEFAULT_LANGUAGE,
+        next_icon_id: 1,
+        entries: vec![],
+        strings: vec![],
+        deps: vec![],
+    };
+    parser.parse()?;
+
+    let mut deps = pp.deps;
+    deps.append(&mut parser.deps);
+    Ok((parser.finish(), deps))
+}
+
+
+/// A preprocessed line, and where it came from
+struct Line {
+    text: String,
+    file: Rc<PathBuf>,
+    line: usize,
+}
+
+impl Line {
+    fn error<T, S: AsRef<str>>(&self, msg: S) -> Result<T, Cow<'static, str>> {
+        Err(format!("{}:{}: {}", self.file.display(), self.line, msg.as_ref()).into())
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PpToken {
+    Ident(String),
+    Other(String),
+    Space,
+}
+
+impl PpToken {
+    fn text(&self) -> &str {
+        match self {
+            PpToken::Ident(s) | PpToken::Other(s) => s,
+            PpToken::Space => " ",
+        }
+    }
+}
+
+fn pp_tokenise(line: &str) -> Vec<PpToken> {
+    let mut ret = vec![];
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        if c.is_whitespace() {
+            while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+            ret.push(PpToken::Space);
+        } else if c == '"' || c == '\'' {
+            let mut escaped = false;
+            for (i, n) in chars.by_ref() {
+                end = i + n.len_utf8();
+                if escaped {
+                    escaped = false;
+                } else if n == '\\' {
+                    escaped = true;
+                } else if n == c {
+                    break;
+                }
+            }
+            ret.push(PpToken::Other(line[start..end].to_string()));
+        } else if c.is_ascii_digit() || c == '.' {
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            ret.push(PpToken::Other(line[start..end].to_string()));
+        } else if c.is_alphabetic() || c == '_' {
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            ret.push(PpToken::Ident(line[start..end].to_string()));
+        } else {
+            ret.push(PpToken::Other(c.to_string()));
+        }
+    }
+    ret
+}
+
+fn pp_untokenise(tokens: &[PpToken]) -> String {
+    tokens.iter().map(PpToken::text).collect()
+}
+
+fn trim_spaces(mut tokens: &[PpToken]) -> &[PpToken] {
+    while tokens.first() == Some(&PpToken::Space) {
+        tokens = &tokens[1..];
+    }
+    while tokens.last() == Some(&PpToken::Space) {
+        tokens = &tokens[..tokens.len() - 1];
+    }
+    tokens
+}
+
+
+struct Macro {
+    params: Option<Vec<String>>,
+    body: Vec<PpToken>,
+}
+
+struct Conditional {
+    /// Currently outputting lines
+    active: bool,
+    /// Some branch has been taken already (or the enclosing conditional is inactive)
+    done: bool,
+}
+
+struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    include_dirs: Vec<PathBuf>,
+    once: Vec<PathBuf>,
+    /// Every file read, in order
+    deps: Vec<PathBuf>,
+    lines: Vec<Line>,
+    depth: usize,
+}
+
+impl Preprocessor {
+    /// `NAME body` or `NAME(params) body`, as after `#define`
+    fn define(&mut self, def: &str) -> bool {
+        let tokens = pp_tokenise(def.trim_start());
+        let name = match tokens.first() {
+            Some(PpToken::Ident(name)) => name.clone(),
+            _ => return false,
+        };
+        let (params, body) = if tokens.get(1) == Some(&PpToken::Other("(".to_string())) {
+            let close = match tokens.iter().position(|t| *t == PpToken::Other(")".to_string())) {
+                Some(c) => c,
+                None => return false,
+            };
+            (Some(tokens[2..close].iter().filter_map(|t| if let PpToken::Ident(p) = t { Some(p.clone()) } else { None }).collect()), &tokens[close + 1..])
+        } else {
+            (None, &tokens[1..])
+        };
+        self.macros.insert(name,
+                           Macro {
+                               params,
+                               body: trim_spaces(body).to_vec(),
+                           });
+        true
+    }
+
+    fn file(&mut self, path: &Path) -> Result<(), Cow<'static, str>> {
+        if self.depth > 200 {
+            return Err(format!("{}: #include nested too deeply", path.display()).into());
+        }
+        let data = fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        self.deps.push(path.to_path_buf());
+        let text = decode(&data);
+        let file = Rc::new(path.to_path_buf());
+
+        let mut conditionals: Vec<Conditional> = vec![];
+        let mut lines = strip_comments(&text).split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l).to_string()).enumerate().collect::<Vec<_>>().into_iter();
+        while let Some((idx, mut text)) = lines.next() {
+            while text.ends_with('\\') {
+                text.pop();
+                match lines.next() {
+                    Some((_, next)) => text.push_str(&next),
+                    None => break,
+                }
+            }
+            let line = Line {
+                text,
+                file: file.clone(),
+                line: idx + 1,
+            };
+            let active = conditionals.last().map(|c| c.active).unwrap_or(true);
+
+            let trimmed = line.text.trim_start();
+            if let Some(directive) = trimmed.strip_prefix('#') {
+                let directive = directive.trim_start();
+                let (name, rest) = directive.split_at(directive.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(directive.len()));
+                let rest = rest.trim();
+                match name {
+                    "if" | "ifdef" | "ifndef" => {
+                        let taken = active &&
+                                    match name {
+                            "ifdef" => self.macros.contains_key(rest),
+                            "ifndef" => !self.macros.contains_key(rest),
+                            _ => self.condition(rest, &line)?,
+                        };
+                        conditionals.push(Conditional {
+                            active: taken,
+                            done: taken || !active,
+                        });
+                    }
+                    "elif" => {
+                        let done = match conditionals.last() {
+                            Some(c) => c.done,
+                            None => return line.error("#elif without #if"),
+                        };
+                        let taken = !done && self.condition(rest, &line)?;
+                        let cond = conditionals.last_mut().unwrap();
+                        cond.active = taken;
+                        cond.done = done || taken;
+                    }
+                    "else" => {
+                        match conditionals.last_mut() {
+                            Some(cond) => {
+                                cond.active = !cond.done;
+                                cond.done = true;
+                            }
+                            None => return line.error("#else without #if"),
+                        }
+                    }
+                    "endif" => {
+                        if conditionals.pop().is_none() {
+                            return line.error("#endif without #if");
+                        }
+                    }
+                    _ if !active => {}
+                    "define" => {
+                        if !self.define(rest) {
+                            return line.error(format!("malformed #define {}", rest));
+                        }
+                    }
+                    "undef" => {
+                        self.macros.remove(rest);
+                    }
+                    "include" => self.include(rest, &line)?,
+                    "pragma" => {
+                        if rest == "once" {
+                            self.once.push(path.to_path_buf());
+                        }
+                        // code_page: everything is UTF-8 anyway
+                    }
+                    "error" => return line.error(format!("#error {}", rest)),
+                    "line" | "" => {}
+                    _ => return line.error(format!("unknown preprocessor directive #{}", name)),
+                }
+            } else if active {
+                let text = pp_untokenise(&self.expand(&pp_tokenise(&line.text), &mut vec![]));
+                self.lines.push(Line { text, ..line });
+            }
+        }
+        if !conditionals.is_empty() {
+            return Err(format!("{}: unterminated #if", path.display()).into());
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, what: &str, line: &Line) -> Result<(), Cow<'static, str>> {
+        let what = if what.starts_with('"') || what.starts_with('<') {
+            what.to_string()
+        } else {
+            pp_untokenise(&self.expand(&pp_tokenise(what), &mut vec![])).trim().to_string()
+        };
+        let (name, system) = match (what.strip_prefix('"').and_then(|w| w.strip_suffix('"')), what.strip_prefix('<').and_then(|w| w.strip_suffix('>'))) {
+            (Some(name), _) => (name, false),
+            (None, Some(name)) => (name, true),
+            (None, None) => return line.error(format!("malformed #include {}", what)),
+        };
+
+        let found = if system { None } else { line.file.parent().map(|p| p.join(name)).filter(|p| p.is_file()) }
+            .or_else(|| self.include_dirs.iter().map(|d| d.join(name)).find(|p| p.is_file()));
+        match found {
+            Some(found) => {
+                if self.once.contains(&found) {
+                    return Ok(());
+                }
+                self.depth += 1;
+                let ret = self.file(&found);
+                self.depth -= 1;
+                ret
+            }
+            None if SYSTEM_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)) => Ok(()),
+            None => line.error(format!("{} not found", name)),
+        }
+    }
+
+    fn expand(&self, tokens: &[PpToken], disabled: &mut Vec<String>) -> Vec<PpToken> {
+        let mut ret = vec![];
+        let mut i = 0;
+        while i < tokens.len() {
+            if let PpToken::Ident(name) = &tokens[i] {
+                if let Some(mac) = self.macros.get(name).filter(|_| !disabled.contains(name)) {
+                    match &mac.params {
+                        None => {
+                            disabled.push(name.clone());
+                            ret.extend(self.expand(&mac.body, disabled));
+                            disabled.pop();
+                            i += 1;
+                            continue;
+                        }
+                        Some(params) => {
+                            if let Some((args, after)) = macro_arguments(&tokens[i + 1..]) {
+                                let args = args.into_iter().map(|a| self.expand(trim_spaces(a), disabled)).collect::<Vec<_>>();
+                                let body = mac.body
+                                    .iter()
+                                    .flat_map(|t| match t {
+                                        PpToken::Ident(p) => {
+                                            match params.iter().position(|pp| pp == p) {
+                                                Some(idx) => args.get(idx).cloned().unwrap_or_default(),
+                                                None => vec![t.clone()],
+                                            }
+                                        }
+                                        _ => vec![t.clone()],
+                                    })
+                                    .collect::<Vec<_>>();
+                                disabled.push(name.clone());
+                                ret.extend(self.expand(&body, disabled));
+                                disabled.pop();
+                                i += 1 + after;
+                                continue;
+                            }
+                        }
+                    }
+                }
+            }
+            ret.push(tokens[i].clone());
+            i += 1;
+        }
+        ret
+    }
+
+    fn condition(&self, expr: &str, line: &Line) -> Result<bool, Cow<'static, str>> {
+        let tokens = pp_tokenise(expr);
+        let mut resolved = vec![];
+        let mut i = 0;
+        while i < tokens.len() {
+            if tokens[i] == PpToken::Ident("defined".to_string()) {
+                let rest = &tokens[i + 1..];
+                let skip = rest.iter().take_while(|t| **t == PpToken::Space).count();
+                let (name, used) = match &rest[skip..] {
+                    [PpToken::Ident(name), ..] => (name, skip + 1),
+                    [PpToken::Other(o), tail @ ..] if o == "(" => {
+                        let inner = tail.iter().take_while(|t| **t == PpToken::Space).count();
+                        match &tail[inner..] {
+                            [PpToken::Ident(name), close @ ..] => {
+                                let end = close.iter().take_while(|t| **t == PpToken::Space).count();
+                                if close.get(end) != Some(&PpToken::Other(")".to_string())) {
+                                    return line.error("malformed defined()");
+                                }
+                                (name, skip + 1 + inner + 1 + end + 1)
+                            }
+                            _ => return line.error("malformed defined()"),
+                        }
+                    }
+                    _ => return line.error("malformed defined"),
+                };
+                resolved.push(PpToken::Other(if self.macros.contains_key(name) { "1" } else { "0" }.to_string()));
+                i += 1 + used;
+            } else {
+                resolved.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+
+        let expanded = pp_untokenise(&self.expand(&resolved, &mut vec![]));
+        match PpExpr::new(&expanded).and_then(|mut e| {
+            let v = e.ternary()?;
+            if e.pos != e.tokens.len() { None } else { Some(v) }
+        }) {
+            Some(v) => Ok(v != 0),
+            None => line.error(format!("malformed #if expression: {}", expanded)),
+        }
+    }
+}
+
+/// `( args )` at the start of `tokens`, and how many tokens that was
+fn macro_arguments(tokens: &[PpToken]) -> Option<(Vec<&[PpToken]>, usize)> {
+    let open = tokens.iter().position(|t| *t != PpToken::Space)?;
+    if tokens[open] != PpToken::Other("(".to_string()) {
+        return None;
+    }
+
+    let mut args = vec![];
+    let mut depth = 0;
+    let mut start = open + 1;
+    for (i, t) in tokens.iter().enumerate().skip(open + 1) {
+        match t.text() {
+            "(" => depth += 1,
+            ")" if depth == 0 => {
+                if i != open + 1 || !args.is_empty() {
+                    args.push(&tokens[start..i]);
+                }
+                return Some((args, i + 1));
+            }
+            ")" => depth -= 1,
+            "," if depth == 0 => {
+                args.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+
+/// `#if` expressions, with C semantics
+struct PpExpr {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl PpExpr {
+    fn new(expr: &str) -> Option<PpExpr> {
+        let mut tokens = vec![];
+        let mut chars = expr.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            let mut tok = c.to_string();
+            if c.is_alphanumeric() || c == '_' {
+                while let Some(&n) = chars.peek() {
+                    if !(n.is_alphanumeric() || n == '_') {
+                        break;
+                    }
+                    tok.push(n);
+                    chars.next();
+                }
+            } else if c == '\'' {
+                for n in chars.by_ref() {
+                    tok.push(n);
+                    if n == '\'' {
+                        break;
+                    }
+                }
+            } else if let Some(&n) = chars.peek() {
+                if ["&&", "||", "==", "!=", "<=", ">=", "<<", ">>"].contains(&&*format!("{}{}", c, n)) {
+                    tok.push(n);
+                    chars.next();
+                }
+            }
+            tokens.push(tok);
+        }
+        Some(PpExpr {
+            tokens,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| &t[..])
+    }
+
+    fn eat(&mut self, tok: &str) -> bool {
+        if self.peek() == Some(tok) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ternary(&mut self) -> Option<i64> {
+        let cond = self.binary(0)?;
+        if self.eat("?") {
+            let yes = self.ternary()?;
+            if !self.eat(":") {
+                return None;
+            }
+            let no = self.ternary()?;
+            Some(if cond != 0 { yes } else { no })
+        } else {
+            Some(cond)
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Option<i64> {
+        const LEVELS: &[&[&str]] = &[&["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek().filter(|op| LEVELS[level].contains(op)).map(str::to_string) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = match &op[..] {
+                "||" => (lhs != 0 || rhs != 0) as i64,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" => lhs.checked_shl(rhs as u32)?,
+                ">>" => lhs.checked_shr(rhs as u32)?,
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" => lhs.checked_div(rhs)?,
+                "%" => lhs.checked_rem(rhs)?,
+                _ => unreachable!(),
+            };
+        }
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        let tok = self.peek()?.to_string();
+        self.pos += 1;
+        match &tok[..] {
+            "!" => Some((self.unary()? == 0) as i64),
+            "~" => Some(!self.unary()?),
+            "-" => Some(self.unary()?.wrapping_neg()),
+            "+" => self.unary(),
+            "(" => {
+                let v = self.ternary()?;
+                if self.eat(")") { Some(v) } else { None }
+            }
+            _ if tok.starts_with('\'') => tok.chars().nth(1).map(|c| c as i64),
+            _ if tok.starts_with(|c: char| c.is_ascii_digit()) => parse_int(&tok).map(|(v, _)| v as i64),
+            // Identifiers left after expansion
+            _ if tok.starts_with(|c: char| c.is_alphabetic() || c == '_') => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Decimal, `0x` hexadecimal, or `0` octal, with `L`/`U` suffixes; `.1` is `true` for `L`
+fn parse_int(tok: &str) -> Option<(u32, bool)> {
+    let digits = tok.trim_end_matches(['l', 'L', 'u', 'U']);
+    let long = tok[digits.len()..].contains(['l', 'L']);
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse::<u64>().ok()?
+    };
+    Some((value as u32, long))
+}
+
+
+/// UTF-8, or UTF-16 with a BOM, like Visual Studio writes; anything else is decoded lossily
+fn decode(data: &[u8]) -> String {
+    match data {
+        [0xFF, 0xFE, rest @ ..] => String::from_utf16_lossy(&rest.chunks(2).map(|c| u16::from_le_bytes([c[0], *c.get(1).unwrap_or(&0)])).collect::<Vec<_>>()),
+        [0xFE, 0xFF, rest @ ..] => String::from_utf16_lossy(&rest.chunks(2).map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])).collect::<Vec<_>>()),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+/// Replace comments with whitespace, keeping newlines in place
+fn strip_comments(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                ret.push(c);
+                while let Some(n) = chars.next() {
+                    if n == '\n' {
+                        ret.push(n);
+                        break;
+                    }
+                    ret.push(n);
+                    if n == '\\' {
+                        if let Some(e) = chars.next_if(|&e| e != '\n') {
+                            ret.push(e);
+                        }
+                    } else if n == c {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&n| n != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                ret.push(' ');
+                let mut prev = '\0';
+                for n in chars.by_ref() {
+                    if n == '\n' {
+                        ret.push(n);
+                    }
+                    if prev == '*' && n == '/' {
+                        break;
+                    }
+                    prev = n;
+                }
+            }
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    /// Value, whether `L`-suffixed
+    Int(u32, bool),
+    /// Source between the quotes, whether `L`-prefixed
+    Str(String, bool),
+    Ident(String),
+    Punct(char),
+}
+
+struct Token {
+    tok: Tok,
+    /// Index into preprocessed lines
+    line: usize,
+}
+
+fn lex(lines: &[Line]) -> Result<Vec<Token>, Cow<'static, str>> {
+    let mut ret = vec![];
+    for (idx, line) in lines.iter().enumerate() {
+        let mut chars = line.text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let tok = if c.is_whitespace() {
+                continue;
+            } else if c == '"' || ((c == 'L' || c == 'l') && chars.peek().map(|&(_, n)| n) == Some('"')) {
+                let wide = c != '"';
+                if wide {
+                    chars.next();
+                }
+                let mut raw = String::new();
+                loop {
+                    match chars.next() {
+                        None => return line.error("unterminated string"),
+                        Some((_, '"')) => {
+                            if chars.next_if(|&(_, n)| n == '"').is_some() {
+                                raw.push_str("\"\"");
+                            } else {
+                                break;
+                            }
+                        }
+                        Some((_, '\\')) => {
+                            raw.push('\\');
+                            if let Some((_, e)) = chars.next() {
+                                raw.push(e);
+                            }
+                        }
+                        Some((_, n)) => raw.push(n),
+                    }
+                }
+                Tok::Str(raw, wide)
+            } else if c.is_ascii_digit() {
+                let mut end = start + 1;
+                while let Some((i, n)) = chars.next_if(|&(_, n)| n.is_ascii_alphanumeric()) {
+                    end = i + n.len_utf8();
+                }
+                match parse_int(&line.text[start..end]) {
+                    Some((v, long)) => Tok::Int(v, long),
+                    None => return line.error(format!("malformed number {}", &line.text[start..end])),
+                }
+            } else if c.is_alphabetic() || c == '_' || c == '.' {
+                let mut end = start + c.len_utf8();
+                while let Some((i, n)) = chars.next_if(|&(_, n)| n.is_alphanumeric() || n == '_' || n == '.' || n == '$') {
+                    end = i + n.len_utf8();
+                }
+                Tok::Ident(line.text[start..end].to_string())
+            } else {
+                Tok::Punct(c)
+            };
+            ret.push(Token {
+                tok,
+                line: idx,
+            });
+        }
+    }
+    Ok(ret)
+}
+
+/// Process escapes in string source; `wide` allows for `\xHHHH`
+fn unescape(raw: &str, wide: bool) -> Vec<u16> {
+    let mut ret = vec![];
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.peek() == Some(&'"') => {
+                chars.next();
+                ret.push('"' as u16);
+            }
+            '\\' => {
+                match chars.next() {
+                    Some('n') => ret.push('\n' as u16),
+                    Some('r') => ret.push('\r' as u16),
+                    Some('t') => ret.push('\t' as u16),
+                    Some('a') => ret.push(0x07),
+                    Some('\\') => ret.push('\\' as u16),
+                    Some('"') => ret.push('"' as u16),
+                    Some(x @ ('x' | 'X')) => {
+                        let mut v = 0u32;
+                        let mut n = 0;
+                        while let Some(d) = chars.peek().and_then(|d| d.to_digit(16)).filter(|_| n < if wide { 4 } else { 2 }) {
+                            v = v * 16 + d;
+                            n += 1;
+                            chars.next();
+                        }
+                        if n == 0 {
+                            ret.extend_from_slice(&['\\' as u16, x as u16]);
+                        } else {
+                            ret.push(v as u16);
+                        }
+                    }
+                    Some(d @ '0'..='7') => {
+                        let mut v = d.to_digit(8).unwrap();
+                        let mut n = 1;
+                        while let Some(d) = chars.peek().and_then(|d| d.to_digit(8)).filter(|_| n < 3) {
+                            v = v * 8 + d;
+                            n += 1;
+                            chars.next();
+                        }
+                        ret.push(v as u16);
+                    }
+                    Some(o) => {
+                        ret.push('\\' as u16);
+                        let mut buf = [0; 2];
+                        ret.extend_from_slice(o.encode_utf16(&mut buf));
+                    }
+                    None => ret.push('\\' as u16),
+                }
+            }
+            _ => {
+                let mut buf = [0; 2];
+                ret.extend_from_slice(c.encode_utf16(&mut buf));
+            }
+        }
+    }
+    ret
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum VersionValue {
+    Int(u32, bool),
+    Str(Vec<u16>),
+}
+
+enum VersionNode {
+    Block(String, Vec<VersionNode>),
+    /// Key, (value, preceded by comma)s
+    Value(String, Vec<(VersionValue, bool)>),
+}
+
+/// (block ID, language), strings
+type StringBundle = ((u16, u16), [Option<Vec<u16>>; 16]);
+
+/// The optional statements between a resource type and its data
+struct ResourceOptions {
+    memory_flags: u16,
+    language: u16,
+    version: u32,
+    characteristics: u32,
+}
+
+struct Parser<'p> {
+    tokens: Vec<Token>,
+    pos: usize,
+    lines: &'p [Line],
+    resource_dir: &'p Path,
+    include_dirs: &'p [PathBuf],
+    language: u16,
+    next_icon_id: u16,
+    entries: Vec<ResEntry>,
+    /// In order of appearance
+    strings: Vec<StringBundle>,
+    /// Every resource file read, in order
+    deps: Vec<PathBuf>,
+}
+
+impl<'p> Parser<'p> {
+    fn error<T, S: AsRef<str>>(&self, msg: S) -> Result<T, Cow<'static, str>> {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some(tok) => self.lines[tok.line].error(msg),
+            None => Err(msg.as_ref().to_string().into()),
+        }
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    fn next(&mut self) -> Result<Tok, Cow<'static, str>> {
+        match self.tokens.get(self.pos) {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t.tok.clone())
+            }
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn peek_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Ident(i)) if i.eq_ignore_ascii_case(kw))
+    }
+
+    fn eat_punct(&mut self, p: char) -> bool {
+        if self.peek() == Some(&Tok::Punct(p)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek_begin(&self) -> bool {
+        self.peek() == Some(&Tok::Punct('{')) || self.peek_keyword("BEGIN")
+    }
+
+    fn peek_end(&self) -> bool {
+        self.peek() == Some(&Tok::Punct('}')) || self.peek_keyword("END")
+    }
+
+    fn begin(&mut self) -> Result<(), Cow<'static, str>> {
+        if self.peek_begin() {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error("expected BEGIN or {")
+        }
+    }
+
+    fn end(&mut self) -> bool {
+        if self.peek_end() {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<(u32, bool), Cow<'static, str>> {
+        let (mut lhs, mut lhs_long) = self.term()?;
+        while let Some(Tok::Punct(op @ ('+' | '-' | '|' | '&'))) = self.peek().cloned() {
+            self.pos += 1;
+            let (rhs, rhs_long) = self.term()?;
+            lhs = match op {
+                '+' => lhs.wrapping_add(rhs),
+                '-' => lhs.wrapping_sub(rhs),
+                '|' => lhs | rhs,
+                _ => lhs & rhs,
+            };
+            lhs_long |= rhs_long;
+        }
+        Ok((lhs, lhs_long))
+    }
+
+    fn term(&mut self) -> Result<(u32, bool), Cow<'static, str>> {
+        let (mut lhs, mut lhs_long) = self.unary()?;
+        while let Some(Tok::Punct(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            let (rhs, rhs_long) = self.unary()?;
+            lhs = match op {
+                '*' => lhs.wrapping_mul(rhs),
+                _ => {
+                    match lhs.checked_div(rhs) {
+                        Some(v) => v,
+                        None => return self.error("division by zero"),
+                    }
+                }
+            };
+            lhs_long |= rhs_long;
+        }
+        Ok((lhs, lhs_long))
+    }
+
+    fn unary(&mut self) -> Result<(u32, bool), Cow<'static, str>> {
+        match self.next()? {
+            Tok::Int(v, long) => Ok((v, long)),
+            Tok::Punct('-') => self.unary().map(|(v, long)| (v.wrapping_neg(), long)),
+            Tok::Punct('~') => self.unary().map(|(v, long)| (!v, long)),
+            Tok::Ident(ref i) if i.eq_ignore_ascii_case("NOT") => self.unary().map(|(v, long)| (!v, long)),
+            Tok::Punct('(') => {
+                let ret = self.expr()?;
+                if !self.eat_punct(')') {
+                    return self.error("expected )");
+                }
+                Ok(ret)
+            }
+            Tok::Ident(i) => {
+                self.pos -= 1;
+                self.error(format!("undefined symbol {}", i))
+            }
+            _ => {
+                self.pos -= 1;
+                self.error("expected number")
+            }
+        }
+    }
+
+    fn id(&mut self) -> Result<ResId, Cow<'static, str>> {
+        match self.next()? {
+            Tok::Int(v, _) => Ok(ResId::Ordinal(v as u16)),
+            Tok::Ident(i) => Ok(ResId::Name(i.to_uppercase())),
+            Tok::Str(s, wide) => Ok(ResId::Name(String::from_utf16_lossy(&unescape(&s, wide)).to_uppercase())),
+            Tok::Punct(_) => {
+                self.pos -= 1;
+                self.error("expected resource name or ID")
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Vec<u16>, Cow<'static, str>> {
+        match self.next()? {
+            Tok::Str(s, wide) => Ok(unescape(&s, wide)),
+            _ => {
+                self.pos -= 1;
+                self.error("expected string")
+            }
+        }
+    }
+
+    fn language(&mut self) -> Result<u16, Cow<'static, str>> {
+        let (lang, _) = self.expr()?;
+        if !self.eat_punct(',') {
+            return self.error("expected , between language and sublanguage");
+        }
+        let (sublang, _) = self.expr()?;
+        Ok((((sublang & 0x3F) << 10) | (lang & 0x3FF)) as u16)
+    }
+
+    fn options(&mut self, memory_flags: u16) -> Result<ResourceOptions, Cow<'static, str>> {
+        let mut ret = ResourceOptions {
+            memory_flags,
+            language: self.language,
+            version: 0,
+            characteristics: 0,
+        };
+        while let Some(Tok::Ident(i)) = self.peek().cloned() {
+            match &i.to_uppercase()[..] {
+                "PRELOAD" => ret.memory_flags |= MEMORY_PRELOAD,
+                "LOADONCALL" => ret.memory_flags &= !MEMORY_PRELOAD,
+                "MOVEABLE" => ret.memory_flags |= MEMORY_MOVEABLE,
+                "FIXED" => ret.memory_flags &= !(MEMORY_MOVEABLE | MEMORY_DISCARDABLE),
+                "PURE" => ret.memory_flags |= MEMORY_PURE,
+                "IMPURE" => ret.memory_flags &= !(MEMORY_PURE | MEMORY_DISCARDABLE),
+                "DISCARDABLE" => ret.memory_flags |= MEMORY_DISCARDABLE | MEMORY_MOVEABLE | MEMORY_PURE,
+                "SHARED" | "NONSHARED" => {}
+                "LANGUAGE" => {
+                    self.pos += 1;
+                    ret.language = self.language()?;
+                    continue;
+                }
+                "VERSION" => {
+                    self.pos += 1;
+                    ret.version = self.expr()?.0;
+                    continue;
+                }
+                "CHARACTERISTICS" => {
+                    self.pos += 1;
+                    ret.characteristics = self.expr()?.0;
+                    continue;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        Ok(ret)
+    }
+
+    fn filename(&mut self) -> Result<PathBuf, Cow<'static, str>> {
+        let name = match self.next()? {
+            Tok::Str(s, wide) => String::from_utf16_lossy(&unescape(&s, wide)),
+            Tok::Ident(i) => i,
+            _ => {
+                self.pos -= 1;
+                return self.error("expected filename");
+            }
+        };
+        let name = Path::new(&name);
+
+        let referrer = self.lines[self.tokens[self.pos - 1].line].file.parent().map(Path::to_path_buf);
+        match referrer.into_iter()
+            .chain(Some(self.resource_dir.to_path_buf()))
+            .chain(self.include_dirs.iter().cloned())
+            .map(|d| d.join(name))
+            .chain(Some(name.to_path_buf()))
+            .find(|p| p.is_file()) {
+            Some(p) => Ok(p),
+            None => {
+                self.pos -= 1;
+                self.error(format!("{} not found", name.display()))
+            }
+        }
+    }
+
+    fn read(&mut self, path: &Path) -> Result<Vec<u8>, Cow<'static, str>> {
+        self.deps.push(path.to_path_buf());
+        fs::read(path).or_else(|e| {
+            self.pos -= 1;
+            self.error(format!("Couldn't read {}: {}", path.display(), e))
+        })
+    }
+
+    fn parse(&mut self) -> Result<(), Cow<'static, str>> {
+        while self.peek().is_some() {
+            if self.peek_keyword("LANGUAGE") {
+                self.pos += 1;
+                self.language = self.language()?;
+            } else if self.peek_keyword("STRINGTABLE") {
+                self.pos += 1;
+                self.stringtable()?;
+            } else {
+                let name = self.id()?;
+                self.resource(name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn resource(&mut self, name: ResId) -> Result<(), Cow<'static, str>> {
+        let default_flags = MEMORY_MOVEABLE | MEMORY_PURE;
+        let tp = match self.next()? {
+            Tok::Ident(tp) => {
+                match &tp.to_uppercase()[..] {
+                    "ICON" => {
+                        let opts = self.options(MEMORY_MOVEABLE | MEMORY_PURE | MEMORY_DISCARDABLE)?;
+                        let file = self.filename()?;
+                        return self.icon(name, opts, &file);
+                    }
+                    "VERSIONINFO" => return self.versioninfo(name),
+                    "BITMAP" => {
+                        let opts = self.options(default_flags)?;
+                        let file = self.filename()?;
+                        let data = self.read(&file)?;
+                        // Skip BITMAPFILEHEADER
+                        if data.len() < 14 || !data.starts_with(b"BM") {
+                            self.pos -= 1;
+                            return self.error(format!("{} not a bitmap", file.display()));
+                        }
+                        self.push(ResId::Ordinal(RT_BITMAP), name, opts, data[14..].to_vec());
+                        return Ok(());
+                    }
+                    "RCDATA" => ResId::Ordinal(RT_RCDATA),
+                    "HTML" => ResId::Ordinal(RT_HTML),
+                    "MESSAGETABLE" => ResId::Ordinal(RT_MESSAGETABLE),
+                    unsupported @ ("ACCELERATORS" | "CURSOR" | "DIALOG" | "DIALOGEX" | "FONT" | "MENU" | "MENUEX" | "TOOLBAR" | "DLGINIT" | "DLGINCLUDE" |
+                                   "ANICURSOR" | "ANIICON" | "PLUGPLAY" | "VXD") => {
+                        self.pos -= 1;
+                        return self.error(format!("{} resources not supported by the built-in resource compiler", unsupported));
+                    }
+                    tp => ResId::Name(tp.to_string()),
+                }
+            }
+            Tok::Int(tp, _) => ResId::Ordinal(tp as u16),
+            Tok::Str(s, wide) => ResId::Name(String::from_utf16_lossy(&unescape(&s, wide)).to_uppercase()),
+            Tok::Punct(_) => {
+                self.pos -= 1;
+                return self.error("expected resource type");
+            }
+        };
+
+        let opts = self.options(default_flags)?;
+        let data = if self.peek_begin() {
+            self.raw_data()?
+        } else {
+            let file = self.filename()?;
+            self.read(&file)?
+        };
+        self.push(tp, name, opts, data);
+        Ok(())
+    }
+
+    fn push(&mut self, tp: ResId, name: ResId, opts: ResourceOptions, data: Vec<u8>) {
+        let mut entry = ResEntry::new(tp, name, opts.language, opts.memory_flags, data);
+        entry.version = opts.version;
+        entry.characteristics = opts.characteristics;
+        self.entries.push(entry);
+    }
+
+    fn raw_data(&mut self) -> Result<Vec<u8>, Cow<'static, str>> {
+        self.begin()?;
+        let mut ret = vec![];
+        while !self.end() {
+            match self.peek() {
+                Some(Tok::Str(s, wide)) => {
+                    let (s, wide) = (s.clone(), *wide);
+                    self.pos += 1;
+                    let data = unescape(&s, wide);
+                    if wide {
+                        ret.extend(data.iter().flat_map(|c| c.to_le_bytes()));
+                    } else {
+                        ret.extend(String::from_utf16_lossy(&data).bytes());
+                    }
+                }
+                None => return self.error("unterminated raw data"),
+                _ => {
+                    let (v, long) = self.expr()?;
+                    if long {
+                        ret.extend_from_slice(&v.to_le_bytes());
+                    } else {
+                        ret.extend_from_slice(&(v as u16).to_le_bytes());
+                    }
+                }
+            }
+            self.eat_punct(',');
+        }
+        Ok(ret)
+    }
+
+    fn icon(&mut self, name: ResId, opts: ResourceOptions, file: &Path) -> Result<(), Cow<'static, str>> {
+        let data = self.read(file)?;
+        let u16_at = |at: usize| data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+        let u32_at = |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        if u16_at(0) != Some(0) || u16_at(2) != Some(1) {
+            self.pos -= 1;
+            return self.error(format!("{} not an icon", file.display()));
+        }
+
+        let count = u16_at(4).unwrap_or(0);
+        let mut group = vec![];
+        group.extend_from_slice(&0u16.to_le_bytes());
+        group.extend_from_slice(&1u16.to_le_bytes());
+        group.extend_from_slice(&count.to_le_bytes());
+        for i in 0..count as usize {
+            let entry = 6 + i * 16;
+            let image = match (data.get(entry..entry + 8), u32_at(entry + 8), u32_at(entry + 12)) {
+                (Some(head), Some(size), Some(offset)) => data.get(offset as usize..offset as usize + size as usize).map(|image| (head, image)),
+                _ => None,
+            };
+            let (head, image) = match image {
+                Some(i) => i,
+                None => {
+                    self.pos -= 1;
+                    return self.error(format!("{}: icon image {} truncated", file.display(), i));
+                }
+            };
+
+            let (mut planes, mut bit_count) = (u16::from_le_bytes([head[4], head[5]]), u16::from_le_bytes([head[6], head[7]]));
+            if !image.starts_with(b"\x89PNG") && image.len() >= 16 {
+                // BITMAPINFOHEADER::biPlanes, biBitCount
+                planes = u16::from_le_bytes([image[12], image[13]]);
+                bit_count = u16::from_le_bytes([image[14], image[15]]);
+            }
+
+            let id = self.next_icon_id;
+            self.next_icon_id += 1;
+            self.entries.push(ResEntry::new(ResId::Ordinal(RT_ICON), ResId::Ordinal(id), opts.language, MEMORY_MOVEABLE | MEMORY_DISCARDABLE, image.to_vec()));
+
+            group.extend_from_slice(&head[0..4]);
+            group.extend_from_slice(&planes.to_le_bytes());
+            group.extend_from_slice(&bit_count.to_le_bytes());
+            group.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            group.extend_from_slice(&id.to_le_bytes());
+        }
+
+        self.push(ResId::Ordinal(RT_GROUP_ICON), name, opts, group);
+        Ok(())
+    }
+
+    fn stringtable(&mut self) -> Result<(), Cow<'static, str>> {
+        let opts = self.options(MEMORY_MOVEABLE | MEMORY_PURE | MEMORY_DISCARDABLE)?;
+        self.begin()?;
+        while !self.end() {
+            let (id, _) = self.expr()?;
+            self.eat_punct(',');
+            let mut string = self.string()?;
+            while let Some(Tok::Str(..)) = self.peek() {
+                string.extend(self.string()?);
+            }
+
+            let key = (((id >> 4) + 1) as u16, opts.language);
+            let bundle = match self.strings.iter().position(|(k, _)| *k == key) {
+                Some(b) => b,
+                None => {
+                    self.strings.push((key, Default::default()));
+                    self.strings.len() - 1
+                }
+            };
+            let slot = &mut self.strings[bundle].1[(id & 0xF) as usize];
+            if slot.is_some() {
+                return self.error(format!("duplicate string table ID {}", id));
+            }
+            *slot = Some(string);
+        }
+        Ok(())
+    }
+
+    fn versioninfo(&mut self, name: ResId) -> Result<(), Cow<'static, str>> {
+        // VS_FIXEDFILEINFO sans dwSignature and dwStrucVersion
+        let mut fixed = [0u32; 11];
+        while let Some(Tok::Ident(kw)) = self.peek() {
+            let kw = kw.to_uppercase();
+            match &kw[..] {
+                "FILEVERSION" | "PRODUCTVERSION" => {
+                    self.pos += 1;
+                    let mut parts = [0u32; 4];
+                    for (i, part) in parts.iter_mut().enumerate() {
+                        if i != 0 && !self.eat_punct(',') {
+                            break;
+                        }
+                        *part = self.expr()?.0 & 0xFFFF;
+                    }
+                    let at = if kw == "FILEVERSION" { 0 } else { 2 };
+                    fixed[at] = (parts[0] << 16) | parts[1];
+                    fixed[at + 1] = (parts[2] << 16) | parts[3];
+                }
+                "FILEFLAGSMASK" | "FILEFLAGS" | "FILEOS" | "FILETYPE" | "FILESUBTYPE" => {
+                    self.pos += 1;
+                    let at = ["FILEFLAGSMASK", "FILEFLAGS", "FILEOS", "FILETYPE", "FILESUBTYPE"].iter().position(|k| *k == kw).unwrap();
+                    fixed[4 + at] = self.expr()?.0;
+                }
+                _ => break,
+            }
+        }
+        let opts = self.options(MEMORY_MOVEABLE | MEMORY_PURE)?;
+        let children = self.version_nodes()?;
+
+        let mut data = vec![];
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&52u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        push_utf16z(&mut data, "VS_VERSION_INFO");
+        pad4(&mut data);
+        data.extend_from_slice(&0xFEEF04BDu32.to_le_bytes());
+        data.extend_from_slice(&0x00010000u32.to_le_bytes());
+        for f in fixed {
+            data.extend_from_slice(&f.to_le_bytes());
+        }
+        for child in &children {
+            write_version_node(&mut data, child);
+        }
+        let len = data.len() as u16;
+        data[0..2].copy_from_slice(&len.to_le_bytes());
+
+        self.push(ResId::Ordinal(RT_VERSION), name, opts, data);
+        Ok(())
+    }
+
+    fn version_nodes(&mut self) -> Result<Vec<VersionNode>, Cow<'static, str>> {
+        self.begin()?;
+        let mut ret = vec![];
+        while !self.end() {
+            if self.peek_keyword("BLOCK") {
+                self.pos += 1;
+                let key = String::from_utf16_lossy(&self.string()?);
+                ret.push(VersionNode::Block(key, self.version_nodes()?));
+            } else if self.peek_keyword("VALUE") {
+                self.pos += 1;
+                let key = String::from_utf16_lossy(&self.string()?);
+                let mut values = vec![];
+                loop {
+                    let comma = self.eat_punct(',');
+                    match self.peek() {
+                        Some(Tok::Str(..)) => values.push((VersionValue::Str(self.string()?), comma)),
+                        Some(Tok::Ident(_)) | None if !comma => break,
+                        _ if self.peek_end() && !comma => break,
+                        _ => {
+                            let (v, long) = self.expr()?;
+                            values.push((VersionValue::Int(v, long), comma));
+                        }
+                    }
+                }
+                if values.is_empty() {
+                    return self.error(format!("VALUE {} has no values", key));
+                }
+                if values.iter().any(|(v, _)| matches!(v, VersionValue::Str(_))) && values.iter().any(|(v, _)| matches!(v, VersionValue::Int(..))) {
+                    return self.error(format!("VALUE {} cannot contain both strings and integers", key));
+                }
+                ret.push(VersionNode::Value(key, values));
+            } else {
+                return self.error("expected BLOCK, VALUE, or END");
+            }
+        }
+        Ok(ret)
+    }
+
+    fn finish(mut self) -> Vec<ResEntry> {
+        for ((block, language), strings) in self.strings {
+            let mut data = vec![];
+            for s in strings {
+                let s = s.unwrap_or_default();
+                data.extend_from_slice(&(s.len() as u16).to_le_bytes());
+                data.extend(s.iter().flat_map(|c| c.to_le_bytes()));
+            }
+            self.entries.push(ResEntry::new(ResId::Ordinal(RT_STRING),
+                                            ResId::Ordinal(block),
+                                            language,
+                                            MEMORY_MOVEABLE | MEMORY_PURE | MEMORY_DISCARDABLE,
+                                            data));
+        }
+        self.entries
+    }
+}
+
+fn write_version_node(out: &mut Vec<u8>, node: &VersionNode) {
+    pad4(out);
+    let start = out.len();
+    out.extend_from_slice(&[0, 0]);
+    match node {
+        VersionNode::Block(key, children) => {
+            out.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&1u16.to_le_bytes());
+            push_utf16z(out, key);
+            for child in children {
+                write_version_node(out, child);
+            }
+        }
+        VersionNode::Value(key, values) => {
+            let text = matches!(values[0].0, VersionValue::Str(_));
+            let value_len_at = out.len();
+            out.extend_from_slice(&[0, 0]);
+            out.extend_from_slice(&(text as u16).to_le_bytes());
+            push_utf16z(out, key);
+            pad4(out);
+            let value_start = out.len();
+            for (i, (value, _)) in values.iter().enumerate() {
+                match value {
+                    VersionValue::Int(v, true) => out.extend_from_slice(&v.to_le_bytes()),
+                    VersionValue::Int(v, false) => out.extend_from_slice(&(*v as u16).to_le_bytes()),
+                    VersionValue::Str(s) => {
+                        out.extend(s.iter().flat_map(|c| c.to_le_bytes()));
+                        let terminate = values.get(i + 1).map(|(_, comma)| *comma).unwrap_or(true);
+                        if terminate && s.last() != Some(&0) {
+                            out.extend_from_slice(&[0, 0]);
+                        }
+                    }
+                }
+            }
+            let value_len = (out.len() - value_start) / if text { 2 } else { 1 };
+            out[value_len_at..value_len_at + 2].copy_from_slice(&(value_len as u16).to_le_bytes());
+        }
+    }
+    let len = (out.len() - start) as u16;
+    out[start..start + 2].copy_from_slice(&len.to_le_bytes());
+}
+
+fn push_utf16z(out: &mut Vec<u8>, s: &str) {
+    out.extend(s.encode_utf16().chain(Some(0)).flat_map(|c| c.to_le_bytes()));
+}
+
+
+
+#[test]
+fn matches_llvm_rc() {
+    let (entries, _) = compile(Path::new("test-data/builtin/t.rc"), ["UNUSED=1"], vec![]).unwrap();
+    assert_eq!(self::super::res::write(&entries), &include_bytes!("../test-data/builtin/t.llvm-rc.res")[..]);
+}
+
+#[test]
+fn deps() {
+    let (_, mut deps) = compile(Path::new("test-data/builtin/t.rc"), ["UNUSED=1"], vec![]).unwrap();
+    deps.sort();
+    deps.dedup();
+    assert_eq!(deps, ["t.bin", "t.bmp", "t.ico", "t.rc"].iter().map(|f| Path::new("test-data/builtin").join(f)).collect::<Vec<_>>());
+}
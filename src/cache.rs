@@ -0,0 +1,171 @@
+//! Skipping the resource compiler if nothing changed since the last build script run
+//!
+//! Next to each output, `$OUT_DIR/{prefix}.cache` records a hash of everything that chose how it was compiled
+//! (the compiler and when it was last modified, target, parameters, and environment), followed by the hash of every file it depends on;
+//! if these all still match, the output is reused as-is.
+
+
+use self::super::{CompilerKind, deps};
+use std::path::{PathBuf, Path};
+use std::hash::{Hasher, Hash};
+use std::time::SystemTime;
+use std::ffi::{OsString, OsStr};
+use std::{env, fs};
+
+
+/// 64-bit FNV-1a, for hashes that stay the same between runs (unlike with `DefaultHasher`)
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Fnv1a {
+        Fnv1a(0xcbf29ce484222325)
+    }
+
+    pub fn hash<H: Hash>(val: H) -> u64 {
+        let mut hasher = Fnv1a::new();
+        val.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+
+/// A hash of everything that chose how a resource is compiled for `target`: `compiler` and when it was last modified,
+/// the hash of the `parameters`, and the environment variables (read with `var`) that reach the compiler, cf. [`deps::compiler_env()`]
+pub fn inputs<V: Fn(&str) -> Option<OsString>>(target: &str, compiler: Option<&(CompilerKind, OsString)>, parameters: u64, var: V) -> u64 {
+    let env = deps::compiler_env(target).into_iter().map(|v| (var(&v), v)).collect::<Vec<_>>();
+    let modified = compiler.and_then(|(_, exe)| modified(exe));
+    Fnv1a::hash((env!("CARGO_PKG_VERSION"), target, compiler, modified, parameters, env))
+}
+
+/// When `exe`, a path or a name looked up in `$PATH`, was last modified, if it's there
+fn modified(exe: &OsStr) -> Option<SystemTime> {
+    let exe = Path::new(exe);
+    let mut found = if exe.components().count() > 1 {
+        vec![exe.to_path_buf()]
+    } else {
+        env::split_paths(&env::var_os("PATH")?)
+            .flat_map(|dir| {
+                let mut suffixed = dir.join(exe).into_os_string();
+                suffixed.push(env::consts::EXE_SUFFIX);
+                [dir.join(exe), PathBuf::from(suffixed)]
+            })
+            .collect()
+    };
+    found.retain(|exe| exe.is_file());
+    fs::metadata(found.first()?).and_then(|m| m.modified()).ok()
+}
+
+/// The output and dependencies recorded in `$OUT_DIR/{prefix}.cache`,
+/// if it was compiled from `inputs`, still exists, and none of its dependencies changed
+pub fn lookup(out_dir: &str, prefix: &str, inputs: u64) -> Option<(String, Vec<PathBuf>)> {
+    let cache = fs::read_to_string(cache_file(out_dir, prefix)).ok()?;
+    let mut lines = cache.lines().map(|l| l.split_once(' ')).map(|l| l.and_then(|(hash, path)| Some((u64::from_str_radix(hash, 16).ok()?, path))));
+
+    let (recorded_inputs, out_file) = lines.next()??;
+    if recorded_inputs != inputs || !Path::new(out_file).is_file() {
+        return None;
+    }
+    let mut deps = vec![];
+    for dep in lines {
+        let (hash, dep) = dep?;
+        if file_hash(Path::new(dep))? != hash {
+            return None;
+        }
+        deps.push(PathBuf::from(dep));
+    }
+    Some((out_file.to_string(), deps))
+}
+
+/// The dependencies recorded in `$OUT_DIR/{prefix}.cache`, if any, whether they changed or not
+pub fn recorded_deps(out_dir: &str, prefix: &str) -> Vec<PathBuf> {
+    fs::read_to_string(cache_file(out_dir, prefix))
+        .map(|cache| cache.lines().skip(1).filter_map(|l| l.split_once(' ')).map(|(_, dep)| PathBuf::from(dep)).collect())
+        .unwrap_or_default()
+}
+
+/// Record that `out_file` was compiled from `inputs` and `deps` in `$OUT_DIR/{prefix}.cache`;
+/// if that fails, or a dependency can't be read, the next run will just compile again
+pub fn store(out_dir: &str, prefix: &str, inputs: u64, out_file: &str, deps: &[PathBuf]) {
+    let cache = cache_file(out_dir, prefix);
+    let mut contents = format!("{:016x} {}\n", inputs, out_file);
+    for dep in deps {
+        match (file_hash(dep), dep.to_str()) {
+            (Some(hash), Some(dep)) if !dep.contains('\n') => contents.push_str(&format!("{:016x} {}\n", hash, dep)),
+            _ => {
+                let _ = fs::remove_file(cache);
+                return;
+            }
+        }
+    }
+    let _ = fs::write(cache, contents);
+}
+
+fn cache_file(out_dir: &str, prefix: &str) -> PathBuf {
+    Path::new(out_dir).join(format!("{}.cache", prefix))
+}
+
+fn file_hash(file: &Path) -> Option<u64> {
+    let mut hasher = Fnv1a::new();
+    hasher.write(&fs::read(file).ok()?);
+    Some(hasher.finish())
+}
+
+
+#[test]
+fn round_trip() {
+    let dir = self::super::test_dir("cache");
+    let out_dir = dir.to_str().unwrap();
+    let (out_file, dep) = (dir.join("t.lib"), dir.join("t.h"));
+    fs::write(&out_file, "compiled").unwrap();
+    fs::write(&dep, "#define A 1").unwrap();
+    let deps = vec![PathBuf::from("test-data/builtin/t.rc"), dep.clone()];
+
+    assert_eq!(lookup(out_dir, "t", 1), None);
+    store(out_dir, "t", 1, out_file.to_str().unwrap(), &deps);
+    assert_eq!(lookup(out_dir, "t", 1), Some((out_file.to_str().unwrap().to_string(), deps.clone())));
+    assert_eq!(lookup(out_dir, "t", 2), None);
+    assert_eq!(lookup(out_dir, "u", 1), None);
+
+    fs::write(&dep, "#define A 2").unwrap();
+    assert_eq!(lookup(out_dir, "t", 1), None);
+    assert_eq!(recorded_deps(out_dir, "t"), deps);
+    assert_eq!(recorded_deps(out_dir, "u"), Vec::<PathBuf>::new());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn inputs_change() {
+    let dir = self::super::test_dir("cache-inputs");
+    let exe = dir.join("windres");
+    fs::write(&exe, "").unwrap();
+    let compiler = (CompilerKind::WindRes, exe.clone().into_os_string());
+    let inputs = |set: &'static str, val: &'static str| {
+        inputs("x86_64-pc-windows-gnu",
+               Some(&compiler),
+               1,
+               move |var| if var == set { Some(OsString::from(val)) } else { None })
+    };
+
+    let base = inputs("", "");
+    assert_eq!(inputs("PATH", "/opt/bin"), base);
+    for var in ["MINGW_CHOST", "INCLUDE", "CC_x86_64-pc-windows-gnu", "CFLAGS_x86_64_pc_windows_gnu", "TARGET_CC", "CFLAGS"] {
+        assert_ne!(inputs(var, "x"), base, "{}", var);
+    }
+    assert_ne!(inputs("MINGW_CHOST", "x86_64-w64-mingw32"), inputs("MINGW_CHOST", "i686-w64-mingw32"));
+
+    fs::File::options().write(true).open(&exe).unwrap().set_modified(SystemTime::UNIX_EPOCH).unwrap();
+    assert_ne!(inputs("", ""), base);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,362 @@
+//! Converting resources into a COFF object with `.rsrc$01` and `.rsrc$02` sections, like `cvtres.exe` and `llvm-cvtres`
+//!
+//! The layout matches `llvm-cvtres /TIMESTAMP:0` byte-for-byte:
+//! the directory tree, its strings, and one relocation per resource go in `.rsrc$01`,
+//! the resource data (aligned to 8 bytes) goes in `.rsrc$02`,
+//! and each resource gets a `$R######` symbol the relocations refer to.
+//! Objects from other tools (like `windres`, which puts everything in one `.rsrc` section) can be read back with [`read_ids()`].
+//!
+//! https://learn.microsoft.com/en-us/windows/win32/debug/pe-format#the-rsrc-section
+
+
+use std::collections::{BTreeMap, VecDeque};
+use self::super::res::{ResEntry, ResId};
+use std::borrow::Cow;
+
+
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 18;
+const RELOCATION_SIZE: usize = 10;
+const DIR_TABLE_SIZE: usize = 16;
+const DIR_ENTRY_SIZE: usize = 8;
+const DATA_ENTRY_SIZE: usize = 16;
+
+const IMAGE_FILE_32BIT_MACHINE: u16 = 0x0100;
+const IMAGE_SCN_CNT_INITIALIZED_DATA_MEM_READ: u32 = 0x0000_0040 | 0x4000_0000;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+
+
+/// Target machine of a COFF object
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Machine {
+    /// `IMAGE_FILE_MACHINE_I386`
+    I386,
+    /// `IMAGE_FILE_MACHINE_AMD64`
+    Amd64,
+    /// `IMAGE_FILE_MACHINE_ARM64`
+    Arm64,
+    /// `IMAGE_FILE_MACHINE_ARMNT`
+    ArmNt,
+}
+
+impl Machine {
+    /// Get the machine for a Rust target triple, if it's one Windows supports
+    pub fn from_target(target: &str) -> Option<Machine> {
+        match target.split('-').next().unwrap_or("") {
+            "x86_64" => Some(Machine::Amd64),
+            "i386" | "i586" | "i686" => Some(Machine::I386),
+            "aarch64" | "arm64ec" => Some(Machine::Arm64),
+            "thumbv7a" | "armv7" => Some(Machine::ArmNt),
+            _ => None,
+        }
+    }
+
+    fn id(self) -> u16 {
+        match self {
+            Machine::I386 => 0x014C,
+            Machine::Amd64 => 0x8664,
+            Machine::Arm64 => 0xAA64,
+            Machine::ArmNt => 0x01C4,
+        }
+    }
+
+    /// `IMAGE_REL_*_ADDR32NB`
+    fn addr32nb(self) -> u16 {
+        match self {
+            Machine::I386 => 0x0007,
+            Machine::Amd64 => 0x0003,
+            Machine::Arm64 => 0x0002,
+            Machine::ArmNt => 0x0002,
+        }
+    }
+}
+
+
+#[derive(Default)]
+struct Dir {
+    names: BTreeMap<Vec<u16>, (usize, Node)>,
+    ids: BTreeMap<u16, Node>,
+}
+
+enum Node {
+    Dir(Dir),
+    Data(usize),
+}
+
+impl Dir {
+    fn subdir(&mut self, id: &ResId, strings: &mut Vec<Vec<u16>>) -> &mut Dir {
+        let node = match id {
+            ResId::Ordinal(o) => self.ids.entry(*o).or_insert_with(|| Node::Dir(Dir::default())),
+            ResId::Name(n) => {
+                &mut self.names
+                    .entry(n.encode_utf16().collect())
+                    .or_insert_with_key(|k| {
+                        strings.push(k.clone());
+                        (strings.len() - 1, Node::Dir(Dir::default()))
+                    })
+                    .1
+            }
+        };
+        match node {
+            Node::Dir(d) => d,
+            Node::Data(_) => unreachable!(),
+        }
+    }
+
+    fn children(&self) -> usize {
+        self.names.len() + self.ids.len()
+    }
+
+    fn tree_size(&self) -> usize {
+        DIR_TABLE_SIZE + self.children() * DIR_ENTRY_SIZE + self.names.values().map(|(_, n)| n).chain(self.ids.values()).map(Node::tree_size).sum::<usize>()
+    }
+}
+
+impl Node {
+    fn tree_size(&self) -> usize {
+        match self {
+            Node::Dir(d) => d.tree_size(),
+            Node::Data(_) => DATA_ENTRY_SIZE,
+        }
+    }
+}
+
+
+/// Convert `entries` into a COFF object for `machine`
+///
+/// Fails if two entries have the same type, name, and language.
+pub fn write(entries: &[ResEntry], machine: Machine) -> Result<Vec<u8>, Cow<'static, str>> {
+    // type -> name -> language
+    let mut strings = vec![];
+    let mut root = Dir::default();
+    for (i, e) in entries.iter().enumerate() {
+        let names = root.subdir(&e.tp, &mut strings);
+        let languages = names.subdir(&e.name, &mut strings);
+        if languages.ids.insert(e.language, Node::Data(i)).is_some() {
+            return Err(format!("Duplicate resource: type {}, name {}, language {:#06x}", e.tp, e.name, e.language).into());
+        }
+    }
+
+    let tree_size = root.tree_size();
+    let string_offsets = strings.iter()
+        .scan(tree_size, |offset, s| {
+            let cur = *offset;
+            *offset += 2 + s.len() * 2;
+            Some(cur as u32)
+        })
+        .collect::<Vec<_>>();
+    let strings_size = strings.iter().map(|s| 2 + s.len() * 2).sum::<usize>();
+    let section_one_size = tree_size + align(strings_size, 4);
+
+    let section_one_offset = FILE_HEADER_SIZE + 2 * SECTION_HEADER_SIZE;
+    let relocations_offset = section_one_offset + section_one_size;
+    let section_two_offset = align(relocations_offset + entries.len() * RELOCATION_SIZE, 8);
+    let data_offsets = entries.iter()
+        .scan(0, |offset, e| {
+            let cur = *offset;
+            *offset += align(e.data.len(), 8);
+            Some(cur as u32)
+        })
+        .collect::<Vec<_>>();
+    let section_two_size = entries.iter().map(|e| align(e.data.len(), 8)).sum::<usize>();
+    let symbol_table_offset = align(section_two_offset + section_two_size, 8);
+
+
+    let mut out = Vec::with_capacity(symbol_table_offset + (5 + entries.len()) * SYMBOL_SIZE + 4);
+    out.extend_from_slice(&machine.id().to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // TimeDateStamp
+    out.extend_from_slice(&(symbol_table_offset as u32).to_le_bytes());
+    out.extend_from_slice(&(5 + entries.len() as u32).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // SizeOfOptionalHeader
+    out.extend_from_slice(&IMAGE_FILE_32BIT_MACHINE.to_le_bytes()); // cvtres.exe sets this for all machines
+
+    write_section_header(&mut out, b".rsrc$01", section_one_size, section_one_offset, relocations_offset, entries.len());
+    write_section_header(&mut out, b".rsrc$02", section_two_size, section_two_offset, 0, 0);
+
+
+    // Directory tables breadth-first, each followed by its entries, then all data entries
+    let mut queue = VecDeque::new();
+    queue.push_back(&root);
+    let mut next_level = DIR_TABLE_SIZE + root.children() * DIR_ENTRY_SIZE;
+    let mut data_order = vec![];
+    while let Some(dir) = queue.pop_front() {
+        out.extend_from_slice(&[0; 12]); // Characteristics, TimeDateStamp, MajorVersion, MinorVersion
+        out.extend_from_slice(&(dir.names.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(dir.ids.len() as u16).to_le_bytes());
+
+        for (identifier, node) in dir.names.values().map(|(idx, n)| (string_offsets[*idx] | 0x8000_0000, n)).chain(dir.ids.iter().map(|(id, n)| (*id as u32, n))) {
+            out.extend_from_slice(&identifier.to_le_bytes());
+            match node {
+                Node::Dir(d) => {
+                    out.extend_from_slice(&(next_level as u32 | 0x8000_0000).to_le_bytes());
+                    next_level += DIR_TABLE_SIZE + d.children() * DIR_ENTRY_SIZE;
+                    queue.push_back(d);
+                }
+                Node::Data(i) => {
+                    out.extend_from_slice(&(next_level as u32).to_le_bytes());
+                    next_level += DATA_ENTRY_SIZE;
+                    data_order.push(*i);
+                }
+            }
+        }
+    }
+
+    let mut relocation_addresses = vec![0u32; entries.len()];
+    for i in data_order {
+        relocation_addresses[i] = (out.len() - section_one_offset) as u32;
+        out.extend_from_slice(&0u32.to_le_bytes()); // DataRVA, relocated
+        out.extend_from_slice(&(entries[i].data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 8]); // Codepage, Reserved
+    }
+
+    for s in &strings {
+        out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+        for c in s {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    pad_to(&mut out, section_one_offset + section_one_size);
+
+    // @feat.00 and two symbols for each section come first
+    for (i, addr) in relocation_addresses.iter().enumerate() {
+        out.extend_from_slice(&addr.to_le_bytes());
+        out.extend_from_slice(&(5 + i as u32).to_le_bytes());
+        out.extend_from_slice(&machine.addr32nb().to_le_bytes());
+    }
+    pad_to(&mut out, section_two_offset);
+
+
+    for e in entries {
+        out.extend_from_slice(&e.data);
+        let len = align(out.len(), 8);
+        pad_to(&mut out, len);
+    }
+    pad_to(&mut out, symbol_table_offset);
+
+
+    write_symbol(&mut out, b"@feat.00", 0x11, 0xFFFF, 0);
+    write_symbol(&mut out, b".rsrc$01", 0, 1, 1);
+    write_section_aux(&mut out, section_one_size, entries.len());
+    write_symbol(&mut out, b".rsrc$02", 0, 2, 1);
+    write_section_aux(&mut out, section_two_size, 0);
+    for (i, offset) in data_offsets.iter().enumerate() {
+        let name = format!("$R{:06X}", i & 0xFF_FFFF);
+        let mut short_name = [0; 8];
+        short_name.copy_from_slice(name.as_bytes());
+        write_symbol(&mut out, &short_name, *offset, 2, 0);
+    }
+
+    out.extend_from_slice(&[0; 4]); // Empty string table
+    Ok(out)
+}
+
+/// The type, name, and language of every resource in a COFF object's `.rsrc` or `.rsrc$01` section
+pub fn read_ids(data: &[u8]) -> Result<Vec<(ResId, ResId, u16)>, Cow<'static, str>> {
+    let u16_at = |at: usize| data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or("Truncated object");
+    let u32_at = |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or("Truncated object");
+
+    let sections = FILE_HEADER_SIZE + u16_at(16)? as usize;
+    let rsrc = (0..u16_at(2)? as usize)
+        .map(|i| sections + i * SECTION_HEADER_SIZE)
+        .find(|&header| matches!(data.get(header..header + 8), Some(b".rsrc\0\0\0") | Some(b".rsrc$01")))
+        .ok_or("No .rsrc section")?;
+    let rsrc = u32_at(rsrc + 20)? as usize;
+
+    let id = |entry: usize| -> Result<ResId, Cow<'static, str>> {
+        let id = u32_at(entry)?;
+        if id & 0x8000_0000 == 0 {
+            return Ok(ResId::Ordinal(id as u16));
+        }
+        let string = rsrc + (id & 0x7FFF_FFFF) as usize;
+        let len = u16_at(string)? as usize;
+        let utf16 = (0..len).map(|i| u16_at(string + 2 + i * 2)).collect::<Result<Vec<_>, _>>()?;
+        Ok(ResId::Name(String::from_utf16_lossy(&utf16)))
+    };
+    // The subdirectory each entry of the directory at `dir` points to
+    let entries = |dir: usize| -> Result<Vec<(usize, usize)>, Cow<'static, str>> {
+        let count = u16_at(dir + 12)? as usize + u16_at(dir + 14)? as usize;
+        (0..count)
+            .map(|i| {
+                let entry = dir + DIR_TABLE_SIZE + i * DIR_ENTRY_SIZE;
+                Ok((entry, rsrc + (u32_at(entry + 4)? & 0x7FFF_FFFF) as usize))
+            })
+            .collect()
+    };
+
+    let mut ret = vec![];
+    for (tp, names) in entries(rsrc)? {
+        for (name, languages) in entries(names)? {
+            for (language, _) in entries(languages)? {
+                ret.push((id(tp)?, id(name)?, u32_at(language)? as u16));
+            }
+        }
+    }
+    Ok(ret)
+}
+
+
+fn write_section_header(out: &mut Vec<u8>, name: &[u8; 8], size: usize, offset: usize, relocations_offset: usize, relocations: usize) {
+    out.extend_from_slice(name);
+    out.extend_from_slice(&[0; 8]); // VirtualSize, VirtualAddress
+    out.extend_from_slice(&(size as u32).to_le_bytes());
+    out.extend_from_slice(&(offset as u32).to_le_bytes());
+    out.extend_from_slice(&(relocations_offset as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // PointerToLinenumbers
+    out.extend_from_slice(&(relocations as u16).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // NumberOfLinenumbers
+    out.extend_from_slice(&IMAGE_SCN_CNT_INITIALIZED_DATA_MEM_READ.to_le_bytes());
+}
+
+fn write_symbol(out: &mut Vec<u8>, name: &[u8; 8], value: u32, section: u16, aux: u8) {
+    out.extend_from_slice(name);
+    out.extend_from_slice(&value.to_le_bytes());
+    out.extend_from_slice(&section.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // Type
+    out.push(IMAGE_SYM_CLASS_STATIC);
+    out.push(aux);
+}
+
+fn write_section_aux(out: &mut Vec<u8>, size: usize, relocations: usize) {
+    out.extend_from_slice(&(size as u32).to_le_bytes());
+    out.extend_from_slice(&(relocations as u16).to_le_bytes());
+    out.extend_from_slice(&[0; 12]); // NumberOfLinenumbers, CheckSum, Number, Selection, unused
+}
+
+fn align(n: usize, to: usize) -> usize {
+    n.div_ceil(to) * to
+}
+
+fn pad_to(out: &mut Vec<u8>, len: usize) {
+    out.resize(len, 0);
+}
+
+
+#[test]
+fn matches_llvm_cvtres() {
+    let entries = self::super::res::read(include_bytes!("../test-data/builtin/t.llvm-rc.res")).unwrap();
+    for &(machine, expected) in &[(Machine::I386, &include_bytes!("../test-data/coff/t-X86.obj")[..]),
+                                  (Machine::Amd64, &include_bytes!("../test-data/coff/t-X64.obj")[..]),
+                                  (Machine::Arm64, &include_bytes!("../test-data/coff/t-ARM64.obj")[..]),
+                                  (Machine::ArmNt, &include_bytes!("../test-data/coff/t-ARM.obj")[..])] {
+        assert_eq!(write(&entries, machine).unwrap(), expected, "{:?}", machine);
+    }
+}
+
+#[test]
+fn duplicate() {
+    let entry = ResEntry::new(ResId::Ordinal(10), ResId::Name("DUP".to_string()), 0x0409, 0x30, vec![1, 2, 3]);
+    assert!(write(&[entry.clone(), entry], Machine::Amd64).is_err());
+}
+
+#[test]
+fn ids() {
+    let entries = self::super::res::read(include_bytes!("../test-data/builtin/t.llvm-rc.res")).unwrap();
+    let mut expected = entries.into_iter().map(|e| (e.tp, e.name, e.language)).collect::<Vec<_>>();
+    expected.sort();
+    let mut ids = read_ids(include_bytes!("../test-data/coff/t-X64.obj")).unwrap();
+    ids.sort();
+    assert_eq!(ids, expected);
+    assert!(read_ids(b"not an object").is_err());
+}
@@ -0,0 +1,300 @@
+//! Finding every file a resource script depends on, for `cargo:rerun-if-changed`
+//!
+//! Compilers that know (the built-in one) leave a depfile at `$OUT_DIR/{prefix}.d`;
+//! otherwise the script is preprocessed with `cc`, which writes the depfile (or, for MSVC-likes, `#line` markers),
+//! and the preprocessed output is scanned for resource statements that reference files.
+//! LLVM-RC's input is already preprocessed like that, into `$OUT_DIR/{prefix}-preprocessed.rc`, so it's reused.
+
+
+use self::super::ResourceError;
+use std::path::{PathBuf, Path};
+use std::ffi::{OsString, OsStr};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+
+
+/// Resource statements that don't reference files
+const NOT_FILE_TYPES: &[&str] = &["ACCELERATORS", "DIALOG", "DIALOGEX", "MENU", "MENUEX", "STRINGTABLE", "TEXTINCLUDE", "TOOLBAR", "VERSIONINFO"];
+
+
+/// Emit `cargo:rerun-if-env-changed` for everything that selects or configures the resource compiler for `target`
+pub fn rerun_if_env_changed(target: &str) {
+    for var in ["RC", "EMBED_RESOURCE_COMPILERS"] {
+        cargo_directive!("rerun-if-env-changed={}_{}", var, target);
+        cargo_directive!("rerun-if-env-changed={}_{}", var, target.replace('-', "_"));
+        cargo_directive!("rerun-if-env-changed={}", var);
+    }
+    for var in compiler_env(target) {
+        cargo_directive!("rerun-if-env-changed={}", var);
+    }
+}
+
+/// The environment variables that reach the resource compiler (or the C preprocessor) for `target`, besides the ones choosing it:
+/// `$MINGW_CHOST` (for `windres --target`), `$INCLUDE` (for `RC.EXE`), and `cc`'s `$CC` and `$CFLAGS` (for LLVM-RC)
+pub fn compiler_env(target: &str) -> Vec<String> {
+    let mut vars = vec!["MINGW_CHOST".to_string(), "INCLUDE".to_string(), "CRATE_CC_NO_DEFAULTS".to_string()];
+    for var in ["CC", "CFLAGS"] {
+        vars.extend([format!("{}_{}", var, target), format!("{}_{}", var, target.replace('-', "_")), format!("TARGET_{}", var), var.to_string()]);
+    }
+    vars
+}
+
+/// `resource_file` and everything it pulls in
+pub fn find(target: &str, resource_file: &Path, out_dir: &str, prefix: &str, macros: &[OsString], include_dirs: &[OsString]) -> Vec<PathBuf> {
+    let deps = match fs::read_to_string(Path::new(out_dir).join(format!("{}.d", prefix))) {
+        Ok(depfile) => parse_depfile(&depfile),
+        Err(_) => {
+            let preprocessed = Path::new(out_dir).join(format!("{}-preprocessed.rc", prefix));
+            match fs::read(&preprocessed) {
+                Ok(preprocessed) => {
+                    Ok(scan(resource_file,
+                            &String::from_utf8_lossy(&preprocessed),
+                            &Path::new(out_dir).join(format!("{}-preprocessed.d", prefix)),
+                            include_dirs))
+                }
+                Err(_) => preprocess(target, resource_file, out_dir, prefix, macros, include_dirs),
+            }
+            .unwrap_or_else(|err| {
+                eprintln!("Couldn't find the dependencies of {}: {}; only depending on it",
+                          resource_file.display(),
+                          err.source().map(|s| format!("{}: {}", err, s)).unwrap_or_else(|| err.to_string()));
+                vec![resource_file.to_path_buf()]
+            })
+        }
+    };
+
+    let mut seen = BTreeSet::new();
+    deps.into_iter().filter(|dep| seen.insert(dep.clone())).collect()
+}
+
+/// Emit `cargo:rerun-if-changed` for `resource_file` and `deps`, except what's in `out_dir`
+///
+/// `resource_file` is even if `deps` is empty or doesn't have it, since emitting none at all
+/// would have Cargo rerun the build script whenever any file in the package changes.
+/// Generated resources (like version information, manifests, or [`compile_str()`](crate::compile_str)) aren't:
+/// they're written to `out_dir` on every run, which would have Cargo rerun it every time instead,
+/// so they're left to the files they were generated from
+pub fn rerun_if_changed(resource_file: &Path, deps: &[PathBuf], out_dir: &str) {
+    for dep in Some(resource_file).into_iter().chain(deps.iter().map(PathBuf::as_path).filter(|dep| *dep != resource_file)) {
+        if !dep.starts_with(out_dir) {
+            cargo_directive!("rerun-if-changed={}", dep.display());
+        }
+    }
+}
+
+/// Preprocess `resource_file` into `$OUT_DIR/{prefix}-deps.rc` and return the files it includes and references
+fn preprocess(target: &str, resource_file: &Path, out_dir: &str, prefix: &str, macros: &[OsString], include_dirs: &[OsString])
+              -> Result<Vec<PathBuf>, ResourceError> {
+    let depfile = Path::new(out_dir).join(format!("{}-deps.d", prefix));
+    let _ = fs::remove_file(&depfile);
+
+    let all_include_dirs = Some(OsString::from(out_dir)).into_iter().chain(include_dirs.iter().cloned()).collect::<Vec<_>>();
+    let preprocessed = self::super::preprocess(target, resource_file, macros, &all_include_dirs, Some(&depfile))?;
+    let preprocessed = String::from_utf8_lossy(&preprocessed);
+    let _ = fs::write(Path::new(out_dir).join(format!("{}-deps.rc", prefix)), preprocessed.as_bytes());
+    Ok(scan(resource_file, &preprocessed, &depfile, include_dirs))
+}
+
+/// `resource_file`, the files `depfile` (if any) or the markers in `preprocessed` say it includes,
+/// and the files it references
+fn scan(resource_file: &Path, preprocessed: &str, depfile: &Path, include_dirs: &[OsString]) -> Vec<PathBuf> {
+    let mut deps = vec![resource_file.to_path_buf()];
+    match fs::read_to_string(depfile) {
+        Ok(depfile) => deps.extend(parse_depfile(&depfile)),
+        // MSVC-likes
+        Err(_) => deps.extend(line_markers(preprocessed).into_iter().filter(|f| f.is_file())),
+    }
+
+    let search_dirs = resource_file.parent()
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(include_dirs.iter().map(PathBuf::from))
+        .chain(Some(PathBuf::from(".")))
+        .collect::<Vec<_>>();
+    deps.extend(referenced_files(preprocessed, &search_dirs));
+    deps
+}
+
+
+/// Parse a Make-style depfile, like `cc -MD` writes, into the prerequisites of all targets
+pub fn parse_depfile(depfile: &str) -> Vec<PathBuf> {
+    let mut ret = vec![];
+    let mut cur = String::new();
+    let mut target = true;
+    let mut chars = depfile.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'\n') || chars.peek() == Some(&'\r') => {
+                while chars.next_if(|&c| c == '\r' || c == '\n').is_some() {}
+                if !cur.is_empty() {
+                    ret.push(PathBuf::from(&cur));
+                    cur.clear();
+                }
+            }
+            '\\' if matches!(chars.peek(), Some(' ' | '#' | '\\')) => cur.push(chars.next().unwrap()),
+            '$' if chars.peek() == Some(&'$') => cur.push(chars.next().unwrap()),
+            ':' if target && matches!(chars.peek(), None | Some(' ' | '\t' | '\r' | '\n')) => {
+                target = false;
+                cur.clear();
+            }
+            ' ' | '\t' => {
+                if !cur.is_empty() && !target {
+                    ret.push(PathBuf::from(&cur));
+                }
+                cur.clear();
+            }
+            '\r' | '\n' => {
+                if !cur.is_empty() && !target {
+                    ret.push(PathBuf::from(&cur));
+                }
+                cur.clear();
+                target = true;
+            }
+            c => cur.push(c),
+        }
+    }
+    if !cur.is_empty() && !target {
+        ret.push(PathBuf::from(&cur));
+    }
+    ret
+}
+
+/// Write a Make-style depfile for `target`
+pub fn write_depfile<'d, D: IntoIterator<Item = &'d Path>>(target: &str, deps: D) -> String {
+    let escape = |p: &OsStr| p.to_string_lossy().replace('\\', "\\\\").replace(' ', "\\ ").replace('#', "\\#").replace('$', "$$");
+    let mut ret = format!("{}:", escape(OsStr::new(target)));
+    for dep in deps {
+        ret.push_str(" \\\n  ");
+        ret.push_str(&escape(dep.as_os_str()));
+    }
+    ret.push('\n');
+    ret
+}
+
+
+/// Files named in `# 1 "file"` and `#line 1 "file"` markers
+fn line_markers(preprocessed: &str) -> Vec<PathBuf> {
+    preprocessed.lines().filter_map(line_marker).map(|(_, file)| file).collect()
+}
+
+/// `# 12 "file"` -> `(12, file)`, meaning the next line is line 12 of `file`
+pub fn line_marker(line: &str) -> Option<(u32, PathBuf)> {
+    let line = line.trim_start().strip_prefix('#')?.trim_start();
+    let line = line.strip_prefix("line").unwrap_or(line).trim_start();
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    let lineno = line[..digits].parse().ok()?;
+    let (file, _) = unquote(line[digits..].trim_start())?;
+    if file.starts_with('<') {
+        None
+    } else {
+        Some((lineno, PathBuf::from(file)))
+    }
+}
+
+/// Existing files referenced by statements like `1 ICON "app.ico"` or `2 24 app.manifest`, looked up in the directory of the
+/// script they're in, then `search_dirs`
+fn referenced_files(preprocessed: &str, search_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut ret = vec![];
+    let mut cur_dir = None;
+    for line in preprocessed.lines() {
+        if let Some((_, file)) = line_marker(line) {
+            cur_dir = file.parent().map(Path::to_path_buf);
+            continue;
+        }
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let tokens = match tokenise(line) {
+            Some(t) if t.len() >= 3 => t,
+            _ => continue,
+        };
+        let tp = tokens[1].to_ascii_uppercase();
+        let name = &tokens[tokens.len() - 1];
+        if NOT_FILE_TYPES.contains(&&tp[..]) || name == "BEGIN" || name == "{" {
+            continue;
+        }
+        if let Some(file) = cur_dir.iter().chain(search_dirs).map(|d| d.join(name)).find(|f| f.is_file()) {
+            ret.push(file);
+        }
+    }
+    ret
+}
+
+/// Split on whitespace, unquoting strings; `None` if there are commas, which filenames never follow
+fn tokenise(line: &str) -> Option<Vec<String>> {
+    let mut ret = vec![];
+    let mut line = line.trim_start();
+    while !line.is_empty() {
+        if line.starts_with('"') {
+            let (s, rest) = unquote(line)?;
+            ret.push(s);
+            line = rest;
+        } else {
+            let end = line.find(|c: char| c.is_whitespace() || c == '"').unwrap_or(line.len());
+            if line[..end].contains(',') {
+                return None;
+            }
+            ret.push(line[..end].to_string());
+            line = &line[end..];
+        }
+        line = line.trim_start();
+    }
+    Some(ret)
+}
+
+/// `"str"ing` -> `(str, ing)`, handling `""` and `\\`
+fn unquote(s: &str) -> Option<(String, &str)> {
+    let mut ret = String::new();
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if s[i + 2..].starts_with('"') => {
+                chars.next();
+                ret.push('"');
+            }
+            '"' => return Some((ret, &s[i + 2..])),
+            '\\' if s[i + 2..].starts_with('\\') => {
+                chars.next();
+                ret.push('\\');
+            }
+            c => ret.push(c),
+        }
+    }
+    None
+}
+
+
+#[test]
+fn depfile() {
+    assert_eq!(parse_depfile("out/t.o: t.rc \\\n  /usr/include/a\\ b.h res.h\\\r\n c:\\\\x.h\nother: $$.h\n"),
+               ["t.rc", "/usr/include/a b.h", "res.h", "c:\\x.h", "$.h"].iter().map(PathBuf::from).collect::<Vec<_>>());
+    let deps = [Path::new("t.rc"), Path::new("a b.h"), Path::new("c:\\x.h")];
+    assert_eq!(parse_depfile(&write_depfile("t.res", deps.iter().cloned())), deps);
+}
+
+#[test]
+fn markers() {
+    assert_eq!(line_markers("# 1 \"test-data/builtin/t.rc\"\n# 1 \"<built-in>\"\n#line 3 \"c:\\\\x y\\\\a.h\"\n#pragma code_page(65001)\n"),
+               [PathBuf::from("test-data/builtin/t.rc"), PathBuf::from("c:\\x y\\a.h")]);
+}
+
+#[test]
+fn referenced() {
+    let preprocessed = "# 1 \"test-data/builtin/t.rc\"\n\
+                        1 ICON \"t.ico\"\n\
+                        2 BITMAP DISCARDABLE t.bmp\n\
+                        3 RCDATA { 1, 2 }\n\
+                        4 24 \"nonexistent.manifest\"\n\
+                        5 CUSTOM \"t.bin\"\n\
+                        VALUE \"t.bin\", \"t.bin\"\n\
+                        1 VERSIONINFO t.bin\n\
+                        # 1 \"elsewhere/x.rc\"\n\
+                        6 RCDATA \"256.png\"\n";
+    assert_eq!(referenced_files(preprocessed, &[PathBuf::from("test-data/icon")]),
+               ["test-data/builtin/t.ico", "test-data/builtin/t.bmp", "test-data/builtin/t.bin", "test-data/icon/256.png"]
+                   .iter()
+                   .map(PathBuf::from)
+                   .collect::<Vec<_>>());
+}
@@ -0,0 +1,309 @@
+//! Picking warnings and errors out of resource compiler output, so they can be shown with `cargo:warning`
+//!
+//! Understands
+//!   * `RC.EXE`: `file.rc(3) : error RC2135 : file not found: app.ico`,
+//!   * GCC/Clang (and so windres' preprocessor): `file.rc:3:10: warning: "X" redefined`,
+//!   * windres: `file.rc:3: syntax error`,
+//!   * LLVM-RC and everything else: `llvm-rc: Error parsing file: expected int or string, got <EOF>`,
+//!     kept whole, and joined with the next line if they end in a colon.
+//!
+//! Everything else (banners, progress, &c.) is ignored.
+//!
+//! LLVM-RC is given a preprocessed file and doesn't report line numbers anyway,
+//! so its diagnostics are [located](Locator) in the preprocessed file and mapped back through its line markers.
+
+
+use std::fmt::{self, Display};
+use std::path::{PathBuf, Path};
+use self::super::deps;
+
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        // Messages without a location are whole lines, which already say what they are
+        if let Some(file) = self.file.as_ref() {
+            write!(f, "{}:", file.display())?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+            }
+            f.write_str(match self.severity {
+                Severity::Error => " error: ",
+                Severity::Warning => " warning: ",
+            })?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+
+/// Parse all diagnostics out of a compiler's `output`
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    let mut ret = vec![];
+    let mut lines = output.lines().map(str::trim_end).filter(|l| !l.is_empty()).peekable();
+    while let Some(line) = lines.next() {
+        if let Some(mut diag) = parse_line(line) {
+            if diag.file.is_none() && diag.message.ends_with(':') {
+                if let Some(next) = lines.next_if(|l| parse_line(l).map(|d| d.file.is_none()).unwrap_or(true)) {
+                    diag.message.push(' ');
+                    diag.message.push_str(next.trim());
+                }
+            }
+            ret.push(diag);
+        }
+    }
+    ret
+}
+
+fn parse_line(line: &str) -> Option<Diagnostic> {
+    parse_rc_exe(line).or_else(|| parse_gcc(line)).or_else(|| parse_bare(line))
+}
+
+/// `file(line) : severity CODE : message`
+fn parse_rc_exe(line: &str) -> Option<Diagnostic> {
+    let (location, rest) = line.split_once(") : ")?;
+    let (file, lineno) = location.rsplit_once('(')?;
+    let (severity, rest) = severity_prefix(rest)?;
+    let message = match rest.split_once(" : ") {
+        Some((code, message)) if !code.contains(' ') => format!("{}: {}", code, message),
+        _ => rest.to_string(),
+    };
+    Some(Diagnostic {
+        file: Some(PathBuf::from(file)),
+        line: Some(lineno.parse().ok()?),
+        severity,
+        message,
+    })
+}
+
+/// `[prog: ]file:line:[col:] [severity:] message`
+fn parse_gcc(line: &str) -> Option<Diagnostic> {
+    // Skip past a drive letter
+    let search_from = if line.as_bytes().get(1) == Some(&b':') { 2 } else { 0 };
+    let (file_end, lineno, rest) = line[search_from..]
+        .match_indices(':')
+        .map(|(i, _)| i + search_from)
+        .filter_map(|i| {
+            let rest = &line[i + 1..];
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if digits != 0 && rest[digits..].starts_with(':') {
+                Some((i, rest[..digits].parse().ok()?, &rest[digits + 1..]))
+            } else {
+                None
+            }
+        })
+        .next()?;
+
+    let file = &line[..file_end];
+    let file = match file.split_once(": ") {
+        Some((_, file)) => file, // windres: file.rc
+        None => file,
+    };
+    // Column
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches(':').trim_start();
+    let (severity, message) = match severity_prefix(rest) {
+        Some((severity, message)) => (severity, message.trim_start_matches(':').trim_start()),
+        None => (Severity::Error, rest), // windres' bison
+    };
+    if file.is_empty() || message.is_empty() {
+        return None;
+    }
+    Some(Diagnostic {
+        file: Some(PathBuf::from(file)),
+        line: Some(lineno),
+        severity,
+        message: message.to_string(),
+    })
+}
+
+/// `[prog: ]severity[:] message`
+fn parse_bare(line: &str) -> Option<Diagnostic> {
+    let rest = match line.split_once(": ") {
+        Some((prog, rest)) if !prog.contains(' ') && severity_prefix(line).is_none() => rest,
+        _ => line,
+    };
+    let (severity, _) = severity_prefix(rest.trim_start())?;
+    Some(Diagnostic {
+        file: None,
+        line: None,
+        severity,
+        message: line.trim().to_string(),
+    })
+}
+
+
+/// Maps diagnostics about a preprocessed file back to the files it was preprocessed from
+pub struct Locator<'p> {
+    path: &'p Path,
+    lines: Vec<&'p str>,
+    /// (index in `lines`, line number of the next line, file)
+    markers: Vec<(usize, u32, PathBuf)>,
+}
+
+impl<'p> Locator<'p> {
+    /// Only LLVM-RC output is preprocessed separately, and it's never used on MSVC hosts
+    #[cfg_attr(all(target_os = "windows", target_env = "msvc"), allow(dead_code))]
+    pub fn new(path: &'p Path, preprocessed: &'p str) -> Locator<'p> {
+        let lines = preprocessed.lines().collect::<Vec<_>>();
+        let markers = lines.iter().enumerate().filter_map(|(i, l)| deps::line_marker(l).map(|(lineno, file)| (i, lineno, file))).collect();
+        Locator {
+            path,
+            lines,
+            markers,
+        }
+    }
+
+    /// Point `diag` at the original file and line, if it refers to the preprocessed file, or, failing that,
+    /// if it's an LLVM-RC `Error in ICON statement (ID 1)`, or `Error parsing file: [...], got TOKEN` where `TOKEN` is unique
+    pub fn locate(&self, diag: &mut Diagnostic) {
+        let line = match (diag.file.as_ref(), diag.line) {
+            (Some(file), Some(line)) if file == self.path || file.file_name() == self.path.file_name() => Some(line as usize),
+            (Some(_), _) => return,
+            (None, _) => self.find_statement(&diag.message).or_else(|| self.find_unexpected(&diag.message)),
+        };
+        if let Some((file, line)) = line.and_then(|l| self.original(l)) {
+            diag.file = Some(file);
+            diag.line = Some(line);
+        }
+    }
+
+    /// The 1-based line in the preprocessed file that starts with `ID TYPE`
+    fn find_statement(&self, message: &str) -> Option<usize> {
+        let (_, rest) = message.split_once("Error in ")?;
+        let (tp, rest) = rest.split_once(" statement (ID ")?;
+        let (id, _) = rest.split_once(')')?;
+        self.lines
+            .iter()
+            .position(|l| {
+                let mut tokens = l.split_whitespace();
+                tokens.next().map(|t| t.trim_matches('"').eq_ignore_ascii_case(id)).unwrap_or(false) &&
+                tokens.next().map(|t| t.eq_ignore_ascii_case(tp)).unwrap_or(false)
+            })
+            .map(|i| i + 1)
+    }
+
+    /// The 1-based line in the preprocessed file that has the only instance of the unexpected token
+    fn find_unexpected(&self, message: &str) -> Option<usize> {
+        let (_, token) = message.rsplit_once(", got ")?;
+        let mut found = self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| !l.trim_start().starts_with('#'))
+            .filter(|(_, l)| l.split(|c: char| c.is_whitespace() || c == ',').any(|t| t == token));
+        match (found.next(), found.next()) {
+            (Some((i, l)), None) if l.matches(token).count() == 1 => Some(i + 1),
+            _ => None,
+        }
+    }
+
+    /// The file and line that the 1-based line `line` of the preprocessed file came from
+    fn original(&self, line: usize) -> Option<(PathBuf, u32)> {
+        let (idx, lineno, file) = self.markers.iter().rev().find(|(idx, ..)| idx + 1 < line)?;
+        Some((file.clone(), *lineno + (line - idx - 2) as u32))
+    }
+}
+
+
+/// `error`, `fatal error`, or `warning`, case-insensitively, as a whole word
+fn severity_prefix(s: &str) -> Option<(Severity, &str)> {
+    for (prefix, severity) in [("fatal error", Severity::Error), ("error", Severity::Error), ("warning", Severity::Warning)] {
+        if s.len() >= prefix.len() && s.is_char_boundary(prefix.len()) && s[..prefix.len()].eq_ignore_ascii_case(prefix) {
+            let rest = &s[prefix.len()..];
+            if rest.is_empty() || rest.starts_with([' ', ':']) {
+                return Some((severity, rest.trim_start()));
+            }
+        }
+    }
+    None
+}
+
+
+#[test]
+fn rc_exe() {
+    assert_eq!(parse("Microsoft (R) Windows (R) Resource Compiler Version 10.0.10011.16384\r\n\
+                      Copyright (C) Microsoft Corporation.  All rights reserved.\r\n\
+                      \r\n\
+                      C:\\app\\app.rc(3) : error RC2135 : file not found: app.ico\r\n\
+                      C:\\app\\app.rc(5) : warning RC4005 : 'X' : redefinition\r\n"),
+               [Diagnostic {
+                    file: Some(PathBuf::from("C:\\app\\app.rc")),
+                    line: Some(3),
+                    severity: Severity::Error,
+                    message: "RC2135: file not found: app.ico".to_string(),
+                },
+                Diagnostic {
+                    file: Some(PathBuf::from("C:\\app\\app.rc")),
+                    line: Some(5),
+                    severity: Severity::Warning,
+                    message: "RC4005: 'X' : redefinition".to_string(),
+                }]);
+}
+
+#[test]
+fn gcc_windres() {
+    assert_eq!(parse("app.rc:2:9: warning: \"X\" redefined\n\
+                      C:/app/app.rc:1:10: fatal error: nope.h: No such file or directory\n\
+                      compilation terminated.\n\
+                      windres: app.rc:7: syntax error\n\
+                      windres: preprocessing failed.\n")
+                   .into_iter()
+                   .map(|d| d.to_string())
+                   .collect::<Vec<_>>(),
+               ["app.rc:2: warning: \"X\" redefined", "C:/app/app.rc:1: error: nope.h: No such file or directory", "app.rc:7: error: syntax error"]);
+}
+
+#[test]
+fn locator() {
+    let preprocessed = "# 1 \"app.rc\"\n\
+                        # 1 \"<built-in>\"\n\
+                        # 1 \"app.rc\"\n\
+                        \n\
+                        # 1 \"include/res.h\" 1\n\
+                        \n\
+                        # 3 \"app.rc\" 2\n\
+                        1 ICON \"nope.ico\"\n\
+                        APP RCDATA { 1, 2 FOO }\n";
+    let locator = Locator::new(Path::new("out/app-preprocessed.rc"), preprocessed);
+    let located = |output: &str| {
+        parse(output)
+            .into_iter()
+            .map(|mut d| {
+                locator.locate(&mut d);
+                d.to_string()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(located("llvm-rc: Error in ICON statement (ID 1): \nerror : file not found : nope.ico\n"),
+               ["app.rc:3: error: llvm-rc: Error in ICON statement (ID 1): error : file not found : nope.ico"]);
+    assert_eq!(located("llvm-rc: Error in RCDATA statement (ID app): \nerror : something\n"),
+               ["app.rc:4: error: llvm-rc: Error in RCDATA statement (ID app): error : something"]);
+    assert_eq!(located("llvm-rc: Error parsing file: expected int or string, got FOO\n"),
+               ["app.rc:4: error: llvm-rc: Error parsing file: expected int or string, got FOO"]);
+    assert_eq!(located("out/app-preprocessed.rc:6:1: warning: odd\n"), ["include/res.h:1: warning: odd"]);
+    assert_eq!(located("llvm-rc: Error parsing file: expected int or string, got 1\n"),
+               ["llvm-rc: Error parsing file: expected int or string, got 1"]);
+}
+
+#[test]
+fn llvm_rc() {
+    assert_eq!(parse("llvm-rc: Error in ICON statement (ID 1): \nerror : file not found : nope.ico\n\
+                      llvm-rc: Error parsing file: expected int or string, got <EOF>\n")
+                   .into_iter()
+                   .map(|d| d.to_string())
+                   .collect::<Vec<_>>(),
+               ["llvm-rc: Error in ICON statement (ID 1): error : file not found : nope.ico",
+                "llvm-rc: Error parsing file: expected int or string, got <EOF>"]);
+}
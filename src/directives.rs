@@ -0,0 +1,130 @@
+//! Printing directives for Cargo, like the ones linking resources to their targets
+//!
+//! Cargo 1.77 added `cargo::` directives, which can't be mistaken for metadata, but only accepts them from packages whose
+//! `rust-version` is at least that; older Cargos would read them as metadata, so that's also what decides it here,
+//! from `$CARGO_PKG_RUST_VERSION`, instead of running `rustc` or `cargo` to ask for their versions.
+//!
+//! https://doc.rust-lang.org/cargo/reference/build-scripts.html#outputs-of-the-build-script
+
+
+use self::super::{ResourceError, LinkTarget, OUTSIDE_CARGO, write_archive, targets};
+use std::sync::{PoisonError, Mutex};
+use std::cell::Cell;
+use std::path::Path;
+use std::{env, fmt};
+
+
+/// Everything linked to [`LinkTarget::Dependents`] in this run
+static EXPORTED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// Everything linked to this package's own targets in this run, and where
+static LINKED: Mutex<Vec<(String, LinkTarget)>> = Mutex::new(vec![]);
+
+
+/// Print `directive` (like `rerun-if-changed=path`) for Cargo, unless [`OUTSIDE_CARGO`](crate::OUTSIDE_CARGO)
+pub fn print(directive: fmt::Arguments) {
+    if !OUTSIDE_CARGO.with(Cell::get) {
+        println!("{}{}", if double_colon() { "cargo::" } else { "cargo:" }, directive);
+    }
+}
+
+/// Set `$DEP_{links}_{key}` in the build scripts of crates depending on this one
+pub fn metadata(key: &str, value: &str) {
+    if double_colon() {
+        print(format_args!("metadata={}={}", key, value));
+    } else {
+        print(format_args!("{}={}", key, value));
+    }
+}
+
+/// Link `out_file`, compiled for `target`, to `link_target`, or, if that's only the library,
+/// a `{prefix}` static library holding it, written to `$OUT_DIR`
+pub fn link(link_target: &LinkTarget, target: &str, prefix: &str, out_file: &str) -> Result<(), ResourceError> {
+    if *link_target != LinkTarget::Dependents {
+        LINKED.lock().unwrap_or_else(PoisonError::into_inner).push((out_file.to_string(), link_target.clone()));
+    }
+    match link_target {
+        LinkTarget::AllBins => {
+            let hasbins = match targets::binaries() {
+                Ok(bins) => {
+                    eprintln!("Final verdict: crate has binaries: {:?}", bins);
+                    !bins.is_empty()
+                }
+                Err(err) => {
+                    eprintln!("Couldn't find the binaries: {}; assuming src/main.rs or S_ISDIR(src/bin/)", err);
+                    Path::new("src/main.rs").exists() || Path::new("src/bin").is_dir()
+                }
+            };
+
+            if hasbins {
+                print(format_args!("rustc-link-arg-bins={}", out_file));
+            } else {
+                // Only links to the calling crate's library
+                let out_dir = env::var("OUT_DIR").map_err(|_| ResourceError::MissingEnv("OUT_DIR"))?;
+                write_archive(target, &out_dir, prefix, out_file)?;
+                print(format_args!("rustc-link-search=native={}", out_dir));
+                print(format_args!("rustc-link-lib=static:-bundle,+whole-archive={}", prefix));
+            }
+        }
+        LinkTarget::Bins(bins) => {
+            for bin in bins {
+                print(format_args!("rustc-link-arg-bin={}={}", bin, out_file));
+            }
+        }
+        LinkTarget::Tests => print(format_args!("rustc-link-arg-tests={}", out_file)),
+        LinkTarget::Benchmarks => print(format_args!("rustc-link-arg-benches={}", out_file)),
+        LinkTarget::Examples => print(format_args!("rustc-link-arg-examples={}", out_file)),
+        LinkTarget::Everything => print(format_args!("rustc-link-arg={}", out_file)),
+        LinkTarget::Cdylib => print(format_args!("rustc-link-arg-cdylib={}", out_file)),
+        LinkTarget::Dependents => {
+            // Becomes $DEP_{links}_RESOURCE in dependents' build scripts; only the last value counts, so it lists all of them
+            let mut exported = EXPORTED.lock().unwrap_or_else(PoisonError::into_inner);
+            if !exported.iter().any(|e| e == out_file) {
+                exported.push(out_file.to_string());
+            }
+            if let Ok(exported) = env::join_paths(exported.iter()) {
+                metadata("RESOURCE", &exported.to_string_lossy());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What's been linked in this run to anything overlapping `targets`, cf. [`LinkTarget::overlaps()`]
+pub fn linked_to(targets: &[LinkTarget]) -> Vec<String> {
+    let mut ret = vec![];
+    for (out_file, linked) in LINKED.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+        if targets.iter().any(|t| t.overlaps(linked)) && !ret.contains(out_file) {
+            ret.push(out_file.clone());
+        }
+    }
+    ret
+}
+
+
+/// Whether the package being built can use `cargo::` directives
+fn double_colon() -> bool {
+    env::var("CARGO_PKG_RUST_VERSION").map(|rv| double_colon_since(&rv)).unwrap_or(false)
+}
+
+/// Whether `rust-version = "{rust_version}"` (empty if none) is at least 1.77
+fn double_colon_since(rust_version: &str) -> bool {
+    let mut parts = rust_version.split('.').map(|p| p.parse::<u64>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(major), minor) => (major, minor.unwrap_or(0)) >= (1, 77),
+        (None, _) => false,
+    }
+}
+
+
+#[test]
+fn rust_versions() {
+    assert!(!double_colon_since(""));
+    assert!(!double_colon_since("1"));
+    assert!(!double_colon_since("1.76"));
+    assert!(!double_colon_since("1.76.0"));
+    assert!(double_colon_since("1.77"));
+    assert!(double_colon_since("1.77.2"));
+    assert!(double_colon_since("1.85"));
+    assert!(double_colon_since("2"));
+}
@@ -0,0 +1,194 @@
+use std::process::{ExitStatus, Command, Output};
+use self::super::{CompilerKind, diagnostics};
+use std::fmt::{self, Display};
+use std::path::{PathBuf, Path};
+use std::ffi::OsString;
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::error;
+use std::io;
+
+
+/// Why a resource couldn't be compiled, carried by [`CompilationResult::NotAttempted`](crate::CompilationResult::NotAttempted)
+/// and [`CompilationResult::Failed`](crate::CompilationResult::Failed)
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ResourceError {
+    /// None of the resource compilers in `tried` could be run
+    CompilerNotFound { tried: Vec<PathBuf>, },
+    /// `$RC_$TARGET`, `$RC_${TARGET//-/_}`, or `$RC` runs, but isn't a resource compiler we know how to drive
+    UnknownCompiler(PathBuf),
+    /// A [`CompilerPreference`](crate::CompilerPreference) of a kind this host can't drive
+    UnsupportedCompiler { exe: PathBuf, kind: CompilerKind, },
+    /// None of the [`CompilerPreference`](crate::CompilerPreference)s worked, for these reasons, in order
+    NoUsableCompiler(Vec<ResourceError>),
+    /// The resource compiler ran, but exited with `status`
+    CompilerFailed {
+        exe: PathBuf,
+        status: ExitStatus,
+        /// What it had to say about it: standard output followed by standard error, since `RC.EXE` only uses the former
+        output: String,
+        /// The warnings and errors in `output`, as `file:line: error: message`, pointing at the original files where possible
+        diagnostics: Vec<String>,
+    },
+    /// The C preprocessor step (used with LLVM-RC) failed on `file`
+    PreprocessFailed { file: PathBuf, source: Arc<dyn error::Error + Send + Sync>, },
+    /// Reading or writing `path` failed
+    Io { path: PathBuf, source: Arc<io::Error>, },
+    /// The path is not valid UTF-8 or has no file name
+    InvalidPath(PathBuf),
+    /// The macro is not valid UTF-8, which everything but `RC.EXE` and `windres` needs
+    InvalidMacro(OsString),
+    /// Cargo didn't set this environment variable (are we running outside a build script?
+    /// or, for `$CARGO_MANIFEST_LINKS`, linking to [`LinkTarget::Dependents`](crate::LinkTarget::Dependents) without a `links` key?)
+    MissingEnv(&'static str),
+    /// The dependency with this `links` key didn't export a resource (it's not building for Windows or couldn't compile it?)
+    MissingDependency(String),
+    /// `Cargo.toml` isn't valid TOML
+    InvalidCargoToml { path: PathBuf, message: String, },
+    /// The package has no binary named `name`; these are the ones it has
+    UnknownBinary { name: String, bins: Vec<String>, },
+    /// Both of these resources, which would be linked into the same binaries, have an application manifest with ID `id`
+    ConflictingManifests { id: String, first: PathBuf, second: PathBuf, },
+    /// The resource script or the generated resources are invalid or unsupported
+    /// (by the built-in compiler, or while converting to an object)
+    InvalidResource(Cow<'static, str>),
+}
+
+impl ResourceError {
+    pub(crate) fn io(path: &Path, source: io::Error) -> ResourceError {
+        ResourceError::Io {
+            path: path.to_path_buf(),
+            source: Arc::new(source),
+        }
+    }
+
+    pub(crate) fn preprocess_failed(file: &Path, source: cc::Error) -> ResourceError {
+        ResourceError::PreprocessFailed {
+            file: file.to_path_buf(),
+            source: Arc::new(source),
+        }
+    }
+}
+
+impl Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ResourceError::CompilerNotFound { tried } => {
+                f.write_str("missing compiler: tried ")?;
+                for (i, exe) in tried.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", exe.display())?;
+                }
+                Ok(())
+            }
+            ResourceError::UnknownCompiler(exe) => write!(f, "unknown RC compiler variant: {}", exe.display()),
+            ResourceError::UnsupportedCompiler { exe, kind } => write!(f, "{} ({}) can't be used on this host", exe.display(), kind),
+            ResourceError::NoUsableCompiler(errors) => {
+                f.write_str("no usable compiler")?;
+                for (i, err) in errors.iter().enumerate() {
+                    f.write_str(if i == 0 { ": " } else { "; " })?;
+                    Display::fmt(err, f)?;
+                }
+                Ok(())
+            }
+            ResourceError::CompilerFailed { exe, status, output, diagnostics } => {
+                write!(f, "{} failed with {}", exe.display(), status)?;
+                if !diagnostics.is_empty() {
+                    for diag in diagnostics {
+                        write!(f, "\n{}", diag)?;
+                    }
+                } else if !output.trim().is_empty() {
+                    write!(f, ":\n{}", output.trim_end())?;
+                }
+                Ok(())
+            }
+            ResourceError::PreprocessFailed { file, .. } => write!(f, "couldn't preprocess \"{}\"", file.display()),
+            ResourceError::Io { path, .. } => write!(f, "couldn't access \"{}\"", path.display()),
+            ResourceError::InvalidPath(path) => write!(f, "invalid path \"{}\": must be UTF-8 and have a file name", path.display()),
+            ResourceError::InvalidMacro(m) => write!(f, "invalid macro \"{}\": must be UTF-8", m.to_string_lossy()),
+            ResourceError::MissingEnv(var) => write!(f, "no ${}", var),
+            ResourceError::MissingDependency(links) => write!(f, "no resource exported by the dependency with links = \"{}\"", links),
+            ResourceError::InvalidCargoToml { path, message } => write!(f, "couldn't parse \"{}\": {}", path.display(), message),
+            ResourceError::UnknownBinary { name, bins } => {
+                write!(f, "no binary named \"{}\"", name)?;
+                if bins.is_empty() {
+                    f.write_str(": the package has none")
+                } else {
+                    write!(f, ": the package has \"{}\"", bins.join("\", \""))
+                }
+            }
+            ResourceError::ConflictingManifests { id, first, second } => {
+                write!(f, "\"{}\" and \"{}\" both have a manifest with ID {}", first.display(), second.display(), id)
+            }
+            ResourceError::InvalidResource(err) => f.write_str(err),
+        }
+    }
+}
+
+impl error::Error for ResourceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ResourceError::PreprocessFailed { source, .. } => Some(&**source),
+            ResourceError::Io { source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+
+/// Run `cmd` (which runs `exe`), echoing its output to our standard error and its diagnostics as `cargo:warning`s
+/// (Cargo only shows the former if we fail), [located](diagnostics::Locator) with `locator`, if any
+pub fn run(cmd: &mut Command, exe: &Path, locator: Option<&diagnostics::Locator>) -> Result<Output, ResourceError> {
+    let output = cmd.output().map_err(|e| if e.kind() == io::ErrorKind::NotFound {
+            ResourceError::CompilerNotFound { tried: vec![exe.to_path_buf()] }
+        } else {
+            ResourceError::io(exe, e)
+        })?;
+    let said = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    eprint!("{}", said);
+    let mut diagnostics = diagnostics::parse(&said);
+    for diag in &mut diagnostics {
+        if let Some(locator) = locator {
+            locator.locate(diag);
+        }
+        cargo_directive!("warning={}", diag);
+    }
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(ResourceError::CompilerFailed {
+            exe: exe.to_path_buf(),
+            status: output.status,
+            output: said,
+            diagnostics: diagnostics.iter().map(|d| d.to_string()).collect(),
+        })
+    }
+}
+
+
+#[test]
+fn display() {
+    assert_eq!(ResourceError::CompilerNotFound { tried: vec![PathBuf::from("x86_64-w64-mingw32-windres"), PathBuf::from("llvm-rc")] }.to_string(),
+               "missing compiler: tried x86_64-w64-mingw32-windres, llvm-rc");
+    assert_eq!(ResourceError::MissingEnv("OUT_DIR").to_string(), "no $OUT_DIR");
+    assert_eq!(ResourceError::NoUsableCompiler(vec![ResourceError::CompilerNotFound { tried: vec![PathBuf::from("llvm-rc-18")] },
+                                                    ResourceError::UnsupportedCompiler {
+                                                        exe: PathBuf::from("rc.exe"),
+                                                        kind: CompilerKind::RcExe,
+                                                    }])
+                   .to_string(),
+               "no usable compiler: missing compiler: tried llvm-rc-18; rc.exe (rc.exe) can't be used on this host");
+    assert_eq!(ResourceError::UnknownBinary {
+                       name: "instaler".to_string(),
+                       bins: vec!["app".to_string(), "installer".to_string()],
+                   }
+                   .to_string(),
+               "no binary named \"instaler\": the package has \"app\", \"installer\"");
+
+    let err = ResourceError::io(Path::new("out/x.rc"), io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+    assert_eq!(err.to_string(), "couldn't access \"out/x.rc\"");
+    assert_eq!(error::Error::source(&err).map(|e| e.to_string()), Some("denied".to_string()));
+}
@@ -0,0 +1,117 @@
+//! Assembling `.ico` files from PNGs
+//!
+//! 256x256 images are stored as-is, everything smaller is converted to a 32-bit DIB with an AND mask,
+//! which is what every version of Windows can read.
+//!
+//! https://learn.microsoft.com/en-us/previous-versions/ms997538(v=msdn.10)
+
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::fs;
+
+
+/// Read the PNGs at `pngs`, which must be square and at most 256x256, and lay them out as an `.ico` file, in order
+pub fn ico_from_pngs<P: AsRef<Path>>(pngs: &[P]) -> Result<Vec<u8>, Cow<'static, str>> {
+    if pngs.is_empty() {
+        return Err("Icon with no images".into());
+    }
+
+    let mut images = vec![];
+    for png in pngs {
+        let png = png.as_ref();
+        let data = fs::read(png).map_err(|e| format!("Couldn't read \"{}\": {}", png.display(), e))?;
+        images.push(image_from_png(data).map_err(|e| format!("\"{}\": {}", png.display(), e))?);
+    }
+
+    let mut ret = vec![];
+    ret.extend_from_slice(&0u16.to_le_bytes()); // idReserved
+    ret.extend_from_slice(&1u16.to_le_bytes()); // idType: icon
+    ret.extend_from_slice(&(images.len() as u16).to_le_bytes());
+    let mut offset = 6 + images.len() * 16;
+    for (size, data) in &images {
+        ret.push(*size as u8); // 256 is 0
+        ret.push(*size as u8);
+        ret.push(0); // bColorCount
+        ret.push(0); // bReserved
+        ret.extend_from_slice(&1u16.to_le_bytes()); // wPlanes
+        ret.extend_from_slice(&32u16.to_le_bytes()); // wBitCount
+        ret.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        ret.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += data.len();
+    }
+    for (_, data) in images {
+        ret.extend_from_slice(&data);
+    }
+    Ok(ret)
+}
+
+/// The size and `RT_ICON` data of a PNG
+fn image_from_png(data: Vec<u8>) -> Result<(u32, Vec<u8>), Cow<'static, str>> {
+    let mut decoder = png::Decoder::new(&data[..]);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    if width != height || width == 0 || width > 256 {
+        return Err(format!("{}x{} isn't a valid icon size: must be square and at most 256x256", width, height).into());
+    }
+    if width == 256 {
+        return Ok((width, data));
+    }
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+    let size = width as usize;
+    let bpp = frame.line_size / size;
+    let bgra = |x: usize, y: usize| -> [u8; 4] {
+        let px = &pixels[y * frame.line_size + x * bpp..];
+        match frame.color_type {
+            png::ColorType::Rgba => [px[2], px[1], px[0], px[3]],
+            png::ColorType::Rgb => [px[2], px[1], px[0], 0xFF],
+            png::ColorType::GrayscaleAlpha => [px[0], px[0], px[0], px[1]],
+            _ => [px[0], px[0], px[0], 0xFF],
+        }
+    };
+
+    let mask_stride = size.div_ceil(32) * 4;
+    let mut ret = Vec::with_capacity(40 + size * size * 4 + mask_stride * size);
+    ret.extend_from_slice(&40u32.to_le_bytes()); // biSize
+    ret.extend_from_slice(&(width as i32).to_le_bytes());
+    ret.extend_from_slice(&(height as i32 * 2).to_le_bytes()); // XOR and AND bitmaps
+    ret.extend_from_slice(&1u16.to_le_bytes()); // biPlanes
+    ret.extend_from_slice(&32u16.to_le_bytes()); // biBitCount
+    ret.extend_from_slice(&0u32.to_le_bytes()); // biCompression: BI_RGB
+    ret.extend_from_slice(&((size * size * 4 + mask_stride * size) as u32).to_le_bytes());
+    ret.extend_from_slice(&[0; 16]); // biXPelsPerMeter, biYPelsPerMeter, biClrUsed, biClrImportant
+
+    // Both bottom-up
+    for y in (0..size).rev() {
+        for x in 0..size {
+            ret.extend_from_slice(&bgra(x, y));
+        }
+    }
+    for y in (0..size).rev() {
+        let mut row = vec![0u8; mask_stride];
+        for x in 0..size {
+            if bgra(x, y)[3] == 0 {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        ret.extend_from_slice(&row);
+    }
+    Ok((width, ret))
+}
+
+
+#[test]
+fn ico_from_pngs_golden() {
+    assert_eq!(ico_from_pngs(&["test-data/icon/16.png", "test-data/icon/32.png", "test-data/icon/256.png"]).unwrap(),
+               &include_bytes!("../test-data/icon/icon.ico")[..]);
+}
+
+#[test]
+fn ico_from_pngs_invalid() {
+    assert!(ico_from_pngs::<&str>(&[]).is_err());
+    assert!(ico_from_pngs(&["test-data/icon/16x8.png"]).is_err());
+    assert!(ico_from_pngs(&["test-data/builtin/t.bin"]).is_err());
+}
@@ -54,6 +54,13 @@
 //!     // or
 //!     embed_resource::compile("checksums.rc", embed_resource::ParamsIncludeDirs(
 //!         &["src/include"])).manifest_required().unwrap();
+//!     // or
+//!     embed_resource::Resource::new("checksums.rc")
+//!         .define("VERSION", Some("000901"))
+//!         .include_dir("src/include")
+//!         .compile()
+//!         .manifest_required()
+//!         .unwrap();
 //! }
 //! ```
 //!
@@ -66,29 +73,62 @@
 //!
 //! ## Errata
 //!
-//! If no `cargo:rerun-if-changed` annotations are generated, Cargo scans the entire build root by default.
-//! Because the first step in building a manifest is an unspecified C preprocessor step with-out the ability to generate the
-//! equivalent of `cc -MD`, we do *not* output said annotation.
+//! `cargo:rerun-if-changed` annotations are generated for the resource file, everything it `#include`s, and every file it
+//! references (icons, manifests, &c.), as found by the built-in compiler or by preprocessing the resource file with the C compiler.
+//! Files that can only be found through the resource compiler's own include path (the Windows SDK headers, say) aren't tracked.
 //!
-//! If scanning is prohibitively expensive, or you have something else that generates the annotations, you may want to spec the
-//! full non-system dependency list for your manifest manually, so:
+//! If something else generates your resource file's dependencies, you may want to spec them manually, so:
 //! ```rust,no_run
-//! println!("cargo:rerun-if-changed=app-name-manifest.rc");
+//! println!("cargo:rerun-if-changed=app-name.exe.manifest");
 //! embed_resource::compile("app-name-manifest.rc", embed_resource::NONE);
 //! ```
 //! for the above example (cf. [#41](https://github.com/nabijaczleweli/rust-embed-resource/issues/41)).
 //!
+//! ## Library crates
+//!
+//! Resources compiled by a library only end up in its dependents' binaries if it exports them:
+//! give it a `links` key, link them to [`LinkTarget::Dependents`], and call [`link_dependency_resources()`]
+//! with that key from the dependents' build scripts:
+//!
+//! ```rust,no_run
+//! # use embed_resource::{LinkTarget, Resource};
+//! // company-branding/build.rs, with links = "company-branding" in its Cargo.toml
+//! Resource::new("branding.rc").link_to(LinkTarget::Dependents).compile().manifest_optional().unwrap();
+//!
+//! // app/build.rs
+//! embed_resource::link_dependency_resources(&["company-branding"], &[LinkTarget::AllBins]).manifest_optional().unwrap();
+//! ```
+//!
 //! # Cross-compilation
 //!
 //! It is possible to embed resources in Windows executables built on non-Windows hosts. There are two ways to do this:
 //!
-//! When targetting `*-pc-windows-gnu`, `*-w64-mingw32-windres` is attempted by default, for `*-pc-windows-msvc` it's `llvm-rc`,
-//! this can be overriden by setting `RC_$TARGET`, `RC_${TARGET//-/_}`, or `RC` environment variables.
+//! When targetting `*-pc-windows-gnu`, `*-w64-mingw32-windres` is attempted by default, then `llvm-windres`;
+//! for `*-pc-windows-msvc` it's `llvm-rc`.
+//! LLVM tools are also looked for with version suffixes in `$PATH` (like `llvm-rc-18`, highest version first),
+//! and in `llvm-config --bindir`.
+//! This can be overriden by setting `RC_$TARGET`, `RC_${TARGET//-/_}`, or `RC` environment variables;
+//! setting them to `zig` uses `zig rc`, which brings its own headers (handy with `cargo-zigbuild`), on Windows hosts too.
 //!
 //! When compiling with LLVM-RC, an external C compiler is used to preprocess the resource,
 //! preloaded with configuration from
 //! [`cc`](https://github.com/alexcrichton/cc-rs#external-configuration-via-environment-variables).
 //!
+//! If neither is available (nor, on Windows hosts, `windres` or `RC.EXE`), a built-in resource compiler is used
+//! (it can also be picked explicitly by setting any of the above to `embed-resource`).
+//! It needs no external tools, but only supports the `LANGUAGE`, `STRINGTABLE`, `VERSIONINFO`, `ICON`, `BITMAP`, `RCDATA`, `HTML`,
+//! `MESSAGETABLE`, and user-defined (like `1 RT_MANIFEST "app.exe.manifest"`) statements and a subset of the C preprocessor;
+//! Windows SDK headers like `<windows.h>` are replaced with the definitions usually used from them in resource scripts.
+//! Unless picked explicitly, resources it can't compile are [`CompilationResult::NotAttempted`], like without a resource compiler.
+//!
+//! ## Choosing the compiler
+//!
+//! [`Resource::compilers()`] or a comma-separated list in `EMBED_RESOURCE_COMPILERS_$TARGET`,
+//! `EMBED_RESOURCE_COMPILERS_${TARGET//-/_}`, or `EMBED_RESOURCE_COMPILERS`
+//! (like `llvm-rc:llvm-rc-18,windres:x86_64-w64-mingw32-windres,builtin`, cf. [`CompilerPreference`])
+//! replace all of the above: the first one that runs is used.
+//! [`ToolchainReport`] (or `embed-resource doctor`) shows what was tried.
+//!
 //! ## Migration
 //! ### 2.x
 //!
@@ -140,9 +180,39 @@ extern crate memchr;
 extern crate vswhom;
 #[cfg(all(target_os = "windows", target_env = "msvc"))]
 extern crate winreg;
-extern crate rustc_version;
+extern crate png;
 extern crate toml;
 
+
+thread_local! {
+    /// Set by [`Resource::compile_to()`], which runs outside of build scripts: no `cargo:` directives are printed
+    static OUTSIDE_CARGO: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Print a directive for Cargo, formatted like `println!()` but without the `cargo:`, cf. [`directives::print()`]
+macro_rules! cargo_directive {
+    ($($arg:tt)*) => {
+        $crate::directives::print(format_args!($($arg)*))
+    };
+}
+
+
+mod resource;
+mod version_info;
+mod manifest;
+mod icon;
+mod deps;
+mod directives;
+mod targets;
+mod cache;
+mod archive;
+mod error;
+mod diagnostics;
+mod toolchain;
+mod builtin;
+mod res;
+mod coff;
+
 #[cfg(not(target_os = "windows"))]
 mod non_windows;
 #[cfg(all(target_os = "windows", target_env = "msvc"))]
@@ -157,12 +227,22 @@ use self::windows_msvc::*;
 #[cfg(all(target_os = "windows", not(target_env = "msvc")))]
 use self::windows_not_msvc::*;
 
-use std::{env, fs};
+pub use self::manifest::{Manifest, DpiAwareness, ExecutionLevel, SupportedOs};
+pub use self::resource::{LinkTarget, Resource};
+pub use self::toolchain::{CompilerPreference, ToolchainCandidate, ToolchainReport, CompilerKind};
+pub use self::error::ResourceError;
+pub use self::version_info::VersionInfo;
+
+use std::{env, fs, io};
+use self::resource::Generated;
+use self::cache::Fnv1a;
 use std::ffi::{OsString, OsStr};
-use std::borrow::Cow;
+use std::sync::{PoisonError, Mutex};
+use std::collections::BTreeMap;
 use std::process::Command;
-use toml::Table as TomlTable;
+use std::cell::Cell;
 use std::fmt::{self, Display};
+use std::error::Error;
 use std::path::{Path, PathBuf};
 
 
@@ -316,6 +396,108 @@ fn argument_bundle_into() {
                });
 }
 
+#[test]
+fn macros() {
+    assert_eq!(split_macro(OsStr::new("X=\"1\"")).unwrap(), ("X", Some("\"1\"")));
+    assert_eq!(split_macro(OsStr::new("Y")).unwrap(), ("Y", None));
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        assert!(matches!(split_macro(OsStr::from_bytes(b"X=\xFF")), Err(ResourceError::InvalidMacro(_))));
+    }
+}
+
+/// A fresh `embed-resource-{name}-{pid}` directory for a test to work in
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("embed-resource-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(not(all(target_os = "windows", target_env = "msvc")))]
+#[test]
+fn compiled_once() {
+    let dir = test_dir("compiled-once");
+    let rc = dir.join("once.rc");
+    fs::write(&rc, "1 RCDATA { \"first\" }\n").unwrap();
+
+    let compile = |define| Resource::new(&rc).compilers(&[CompilerPreference::Builtin]).define("X", define).compile_to_for("x86_64-pc-windows-gnu", &dir).unwrap();
+    let first = compile(None);
+    fs::write(&rc, "1 RCDATA { \"second\" }\n").unwrap();
+    assert_eq!(compile(None), first);
+    assert!(memchr::memmem::find(&fs::read(&first).unwrap(), b"first").is_some());
+
+    let second = compile(Some("1"));
+    assert!(memchr::memmem::find(&fs::read(second).unwrap(), b"second").is_some());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(not(all(target_os = "windows", target_env = "msvc")))]
+#[test]
+fn same_stem() {
+    let dir = test_dir("same-stem");
+    for sub in ["a", "b"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+        fs::write(dir.join(sub).join("app.rc"), format!("1 RCDATA {{ \"from {}\" }}\n", sub)).unwrap();
+    }
+
+    let compile = |sub| Resource::new(dir.join(sub).join("app.rc")).compilers(&[CompilerPreference::Builtin]).compile_to_for("x86_64-pc-windows-gnu", &dir).unwrap();
+    let (a, b) = (compile("a"), compile("b"));
+    assert_ne!(a, b);
+    assert!(a.file_name().unwrap().to_str().unwrap().contains("app-"));
+    assert!(memchr::memmem::find(&fs::read(a).unwrap(), b"from a").is_some());
+    assert!(memchr::memmem::find(&fs::read(b).unwrap(), b"from b").is_some());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn res_bytes() {
+    let dir = test_dir("res-bytes");
+
+    let (prefix, _, out_file) = res_impl("x86_64-pc-windows-gnu", Path::new("t"), || Ok(include_bytes!("../test-data/builtin/t.llvm-rc.res")), Some(&dir)).unwrap();
+    assert!(prefix.starts_with("t-"));
+    assert_eq!(fs::read(out_file).unwrap(), &include_bytes!("../test-data/coff/t-X64.obj")[..]);
+    assert!(matches!(res_impl("x86_64-pc-windows-gnu", Path::new("t"), || Ok(b"not a .res"), Some(&dir)),
+                     Err(CompilationResult::Failed(ResourceError::InvalidResource(_)))));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn outside_cargo() {
+    // Like compile(), not having $TARGET isn't a failure
+    assert!(matches!(compile_res_bytes("t", include_bytes!("../test-data/builtin/t.llvm-rc.res")),
+                     CompilationResult::NotAttempted(ResourceError::MissingEnv("TARGET"))));
+    assert!(matches!(link_precompiled_res("test-data/builtin/t.llvm-rc.res"), CompilationResult::NotAttempted(ResourceError::MissingEnv("TARGET"))));
+    assert!(matches!(link_dependency_resources(&["branding"], &[LinkTarget::AllBins]), CompilationResult::NotAttempted(ResourceError::MissingEnv("TARGET"))));
+}
+
+#[test]
+fn dependency_manifests() {
+    let dir = test_dir("dependency-manifests");
+
+    let manifest = |name: &str, id| {
+        let file = dir.join(name);
+        let entries = [res::ResEntry::new(res::ResId::Ordinal(24), res::ResId::Ordinal(id), 0x0409, 0x30, b"<assembly/>".to_vec())];
+        fs::write(&file, coff::write(&entries, coff::Machine::Amd64).unwrap()).unwrap();
+        file
+    };
+    let a = ("a", Some(manifest("a.o", 1).into_os_string()));
+    let b = ("b", Some(env::join_paths([manifest("b.o", 2), manifest("c.o", 1)]).unwrap()));
+    assert_eq!(manifest_ids(&dir.join("c.o")).unwrap(), [res::ResId::Ordinal(1)]);
+
+    let link = |exported: Vec<(&str, Option<OsString>)>| link_exported_resources("x86_64-pc-windows-gnu", exported, &[LinkTarget::Tests]);
+    assert!(matches!(link(vec![a.clone()]), CompilationResult::Ok));
+    match link(vec![a, b]) {
+        CompilationResult::Failed(ResourceError::ConflictingManifests { id, first, second }) => {
+            assert_eq!((&id[..], first, second), ("1", dir.join("a.o"), dir.join("c.o")))
+        }
+        res => panic!("{:?}", res),
+    }
+    assert!(matches!(link(vec![("c", None)]), CompilationResult::NotAttempted(ResourceError::MissingDependency(_))));
+    fs::remove_dir_all(dir).unwrap();
+}
+
 
 /// Result of [`compile()`] and `compile_for*()`
 ///
@@ -325,16 +507,16 @@ fn argument_bundle_into() {
 /// Turn this into a `Result` with `manifest_required()` if the manifest is mandatory, like when configuring entry points or
 /// security.
 #[must_use]
-#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug)]
 pub enum CompilationResult {
     /// not building for windows
     NotWindows,
     /// built, linked
     Ok,
     /// building for windows, but the environment can't compile a resource (most likely due to a missing compiler)
-    NotAttempted(Cow<'static, str>),
+    NotAttempted(ResourceError),
     /// environment can compile a resource, but has failed to do so
-    Failed(Cow<'static, str>),
+    Failed(ResourceError),
 }
 impl CompilationResult {
     /// `Ok(())` if `NotWindows`, `Ok`, or `NotAttempted`; `Err(self)` if `Failed`
@@ -365,16 +547,32 @@ impl Display for CompilationResult {
             CompilationResult::Ok => f.write_str("OK"),
             CompilationResult::NotAttempted(why) => {
                 f.write_str("compilation not attempted: ")?;
-                if !why.contains(' ') {
-                    f.write_str("missing compiler: ")?;
-                }
-                f.write_str(why)
+                write_error_chain(f, why)
             }
-            CompilationResult::Failed(err) => f.write_str(err),
+            CompilationResult::Failed(err) => write_error_chain(f, err),
         }
     }
 }
-impl std::error::Error for CompilationResult {}
+impl Error for CompilationResult {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CompilationResult::NotAttempted(err) |
+            CompilationResult::Failed(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// `err: source: source's source: ...`, since this is usually what ends up `unwrap()`ped
+fn write_error_chain(f: &mut fmt::Formatter<'_>, err: &dyn Error) -> Result<(), fmt::Error> {
+    Display::fmt(err, f)?;
+    let mut source = err.source();
+    while let Some(err) = source {
+        write!(f, ": {}", err)?;
+        source = err.source();
+    }
+    Ok(())
+}
 
 macro_rules! try_compile_impl {
     ($expr:expr) => {
@@ -396,11 +594,12 @@ macro_rules! try_compile_impl {
 ///
 /// `$OUT_DIR` is added to the include search path.
 ///
-/// Note that this does *nothing* if building with rustc before 1.50.0 and there's a library in the crate,
-/// since the resource is linked to the library, if any, instead of the binaries.
+/// Compiling the same resource file with the same parameters again in the same build script
+/// (like with [`compile_for()`] for other binaries) reuses the first compilation;
+/// [`compile_for_targets()`] links one compilation to many targets outright.
 ///
-/// Since rustc 1.50.0, the resource is linked only to the binaries
-/// (unless there are none, in which case it's also linked to the library).
+/// The resource is linked only to the binaries (unless there are none, in which case it's linked to the library,
+/// as a static library with `rustc-link-lib=static:-bundle,+whole-archive`).
 ///
 /// `parameters` are a list of macros to define (directly or via [`ParamsMacros`]), in standard `NAME`/`NAME=VALUE` format,
 /// [`ParamsIncludeDirs`], or [`ParamsMacrosAndIncludeDirs`].
@@ -425,34 +624,12 @@ pub fn compile<T: AsRef<Path>,
                P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
     resource_file: T, parameters: P)
     -> CompilationResult {
-    let (prefix, out_dir, out_file) = try_compile_impl!(compile_impl(resource_file.as_ref(), parameters.into()));
-    let hasbins = fs::read_to_string("Cargo.toml")
-        .unwrap_or_else(|err| {
-            eprintln!("Couldn't read Cargo.toml: {}; assuming src/main.rs or S_ISDIR(src/bin/)", err);
-            String::new()
-        })
-        .parse::<TomlTable>()
-        .unwrap_or_else(|err| {
-            eprintln!("Couldn't parse Cargo.toml: {}; assuming src/main.rs or S_ISDIR(src/bin/)", err);
-            TomlTable::new()
-        })
-        .contains_key("bin") || (Path::new("src/main.rs").exists() || Path::new("src/bin").is_dir());
-    eprintln!("Final verdict: crate has binaries: {}", hasbins);
-
-    if hasbins && rustc_version::version().expect("couldn't get rustc version") >= rustc_version::Version::new(1, 50, 0) {
-        println!("cargo:rustc-link-arg-bins={}", out_file);
-    } else {
-        // Cargo pre-0.51.0 (rustc pre-1.50.0) compat
-        // Only links to the calling crate's library
-        println!("cargo:rustc-link-search=native={}", out_dir);
-        println!("cargo:rustc-link-lib=dylib={}", prefix);
-    }
-    CompilationResult::Ok
+    compile_and_link(resource_file.as_ref(), None, parameters.into(), &[LinkTarget::AllBins], &[])
 }
 
 /// Likewise, but only for select binaries.
 ///
-/// Only available since rustc 1.55.0, does nothing before.
+/// Fails with [`ResourceError::UnknownBinary`] if the package doesn't have one of them.
 ///
 /// # Examples
 ///
@@ -475,17 +652,15 @@ pub fn compile_for<T: AsRef<Path>,
                    P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
     resource_file: T, for_bins: I, parameters: P)
     -> CompilationResult {
-    let (_, _, out_file) = try_compile_impl!(compile_impl(resource_file.as_ref(), parameters.into()));
-    for bin in for_bins {
-        println!("cargo:rustc-link-arg-bin={}={}", bin, out_file);
-    }
-    CompilationResult::Ok
+    compile_and_link(resource_file.as_ref(),
+                     None,
+                     parameters.into(),
+                     &[LinkTarget::Bins(for_bins.into_iter().map(|bin| bin.to_string()).collect())],
+                     &[])
 }
 
 /// Likewise, but only link the resource to test binaries (select types only. unclear which (and likely to change). you may
 /// prefer [`compile_for_everything()`]).
-///
-/// Only available since rustc 1.60.0, does nothing before.
 pub fn compile_for_tests<T: AsRef<Path>,
                          Ms: AsRef<OsStr>,
                          Mi: IntoIterator<Item = Ms>,
@@ -494,14 +669,10 @@ pub fn compile_for_tests<T: AsRef<Path>,
                          P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
     resource_file: T, parameters: P)
     -> CompilationResult {
-    let (_, _, out_file) = try_compile_impl!(compile_impl(resource_file.as_ref(), parameters.into()));
-    println!("cargo:rustc-link-arg-tests={}", out_file);
-    CompilationResult::Ok
+    compile_and_link(resource_file.as_ref(), None, parameters.into(), &[LinkTarget::Tests], &[])
 }
 
 /// Likewise, but only link the resource to benchmarks.
-///
-/// Only available since rustc 1.60.0, does nothing before.
 pub fn compile_for_benchmarks<T: AsRef<Path>,
                               Ms: AsRef<OsStr>,
                               Mi: IntoIterator<Item = Ms>,
@@ -510,14 +681,10 @@ pub fn compile_for_benchmarks<T: AsRef<Path>,
                               P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
     resource_file: T, parameters: P)
     -> CompilationResult {
-    let (_, _, out_file) = try_compile_impl!(compile_impl(resource_file.as_ref(), parameters.into()));
-    println!("cargo:rustc-link-arg-benches={}", out_file);
-    CompilationResult::Ok
+    compile_and_link(resource_file.as_ref(), None, parameters.into(), &[LinkTarget::Benchmarks], &[])
 }
 
 /// Likewise, but only link the resource to examples.
-///
-/// Only available since rustc 1.60.0, does nothing before.
 pub fn compile_for_examples<T: AsRef<Path>,
                             Ms: AsRef<OsStr>,
                             Mi: IntoIterator<Item = Ms>,
@@ -526,15 +693,23 @@ pub fn compile_for_examples<T: AsRef<Path>,
                             P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
     resource_file: T, parameters: P)
     -> CompilationResult {
-    let (_, _, out_file) = try_compile_impl!(compile_impl(resource_file.as_ref(), parameters.into()));
-    println!("cargo:rustc-link-arg-examples={}", out_file);
-    CompilationResult::Ok
+    compile_and_link(resource_file.as_ref(), None, parameters.into(), &[LinkTarget::Examples], &[])
+}
+
+/// Likewise, but only link the resource to the `cdylib`.
+pub fn compile_for_cdylib<T: AsRef<Path>,
+                          Ms: AsRef<OsStr>,
+                          Mi: IntoIterator<Item = Ms>,
+                          Is: AsRef<OsStr>,
+                          Ii: IntoIterator<Item = Is>,
+                          P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
+    resource_file: T, parameters: P)
+    -> CompilationResult {
+    compile_and_link(resource_file.as_ref(), None, parameters.into(), &[LinkTarget::Cdylib], &[])
 }
 
 /// Likewise, but link the resource into *every* artifact: binaries, cdylibs, examples, tests (`[[test]]`/`#[test]`/doctest),
 /// benchmarks, &c.
-///
-/// Only available since rustc 1.50.0, does nothing before.
 pub fn compile_for_everything<T: AsRef<Path>,
                               Ms: AsRef<OsStr>,
                               Mi: IntoIterator<Item = Ms>,
@@ -543,31 +718,542 @@ pub fn compile_for_everything<T: AsRef<Path>,
                               P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
     resource_file: T, parameters: P)
     -> CompilationResult {
-    let (_, _, out_file) = try_compile_impl!(compile_impl(resource_file.as_ref(), parameters.into()));
-    println!("cargo:rustc-link-arg={}", out_file);
+    compile_and_link(resource_file.as_ref(), None, parameters.into(), &[LinkTarget::Everything], &[])
+}
+
+/// Likewise, but link the resource to all of `link_targets`, compiling it only once, like [`Resource::link_to()`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+/// use embed_resource::LinkTarget;
+///
+/// fn main() {
+///     embed_resource::compile_for_targets("app-name-manifest.rc",
+///                                         &[LinkTarget::AllBins, LinkTarget::Tests, LinkTarget::Examples],
+///                                         embed_resource::NONE)
+///         .manifest_optional()
+///         .unwrap();
+/// }
+/// ```
+pub fn compile_for_targets<T: AsRef<Path>,
+                           Ms: AsRef<OsStr>,
+                           Mi: IntoIterator<Item = Ms>,
+                           Is: AsRef<OsStr>,
+                           Ii: IntoIterator<Item = Is>,
+                           P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
+    resource_file: T, link_targets: &[LinkTarget], parameters: P)
+    -> CompilationResult {
+    compile_and_link(resource_file.as_ref(), None, parameters.into(), link_targets, &[])
+}
+
+/// Compile a resource script from memory, as if it were at `resource_file`, and link it like [`compile()`].
+///
+/// The script is written to `$OUT_DIR`, so files it references (and `#include`s) are looked up in the directory `resource_file`
+/// would be in (relative to the crate root), then the include directories in `parameters`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+///
+/// fn main() {
+///     let rc = format!("1 ICON \"{}.ico\"\n", std::env::var("CARGO_PKG_NAME").unwrap());
+///     embed_resource::compile_str("assets/icon.rc", &rc, embed_resource::NONE).manifest_optional().unwrap();
+/// }
+/// ```
+pub fn compile_str<T: AsRef<Path>,
+                   Ms: AsRef<OsStr>,
+                   Mi: IntoIterator<Item = Ms>,
+                   Is: AsRef<OsStr>,
+                   Ii: IntoIterator<Item = Is>,
+                   P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
+    resource_file: T, source: &str, parameters: P)
+    -> CompilationResult {
+    let resource_file = resource_file.as_ref();
+    let stem = try_compile_impl!(resource_file.file_stem()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| CompilationResult::Failed(ResourceError::InvalidPath(resource_file.to_path_buf()))));
+    let generated = Generated {
+        rc: source.to_string(),
+        files: vec![],
+        deps: vec![],
+    };
+
+    let parameters = parameters.into();
+    let resource_file = env::current_dir().map(|cd| cd.join(resource_file)).unwrap_or_else(|_| resource_file.to_path_buf());
+    let include_dirs = resource_file.parent()
+        .map(|parent| parent.as_os_str().to_os_string())
+        .into_iter()
+        .chain(parameters.include_dirs.into_iter().map(|id| id.as_ref().to_os_string()))
+        .collect::<Vec<_>>();
+    compile_and_link(Path::new(&format!("{}-source.rc", stem)),
+                     Some(&generated),
+                     ParameterBundle {
+                         macros: parameters.macros,
+                         include_dirs: &include_dirs,
+                     },
+                     &[LinkTarget::AllBins],
+                     &[])
+}
+
+/// Link an already-compiled resource (the contents of a `.res` file) like [`compile()`], without any resource compiler.
+///
+/// `name` is only used to name the output in `$OUT_DIR`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+///
+/// fn main() {
+///     let res = std::fs::read("assets/app.res").unwrap();
+///     embed_resource::compile_res_bytes("app", &res).manifest_required().unwrap();
+/// }
+/// ```
+pub fn compile_res_bytes(name: &str, res: &[u8]) -> CompilationResult {
+    let target = try_compile_impl!(env_target());
+    let (prefix, _, out_file) = try_compile_impl!(res_impl(&target, Path::new(name), || Ok(res), None));
+    try_compile_impl!(directives::link(&LinkTarget::AllBins, &target, &prefix, &out_file).map_err(CompilationResult::Failed));
+    CompilationResult::Ok
+}
+
+/// Link an already-compiled `.res` file (like from Visual Studio's resource editor), relative to the crate root, like [`compile()`],
+/// without any resource compiler.
+///
+/// It's copied as-is for MSVC targets and converted to a COFF object otherwise.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+///
+/// fn main() {
+///     embed_resource::link_precompiled_res("assets/app.res").manifest_required().unwrap();
+/// }
+/// ```
+pub fn link_precompiled_res<T: AsRef<Path>>(res_file: T) -> CompilationResult {
+    let res_file = res_file.as_ref();
+    let target = try_compile_impl!(env_target());
+    let (prefix, _, out_file) = try_compile_impl!(res_impl(&target,
+                                                           res_file,
+                                                           || {
+                                                               cargo_directive!("rerun-if-changed={}", res_file.display());
+                                                               fs::read(res_file).map_err(|e| ResourceError::io(res_file, e))
+                                                           },
+                                                           None));
+    try_compile_impl!(directives::link(&LinkTarget::AllBins, &target, &prefix, &out_file).map_err(CompilationResult::Failed));
     CompilationResult::Ok
 }
 
+/// Link the resources exported by the dependencies with these `links` keys (with [`LinkTarget::Dependents`])
+/// to all of `link_targets`.
+///
+/// Call this after compiling the crate's own resources, if any: if any two of them (or the dependencies')
+/// that'd be linked to the same artifact have an application manifest with the same ID, this fails, since only one could be used.
+///
+/// Dependencies that didn't export anything make this return [`CompilationResult::NotAttempted`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+/// use embed_resource::LinkTarget;
+///
+/// fn main() {
+///     embed_resource::compile("app.rc", embed_resource::NONE).manifest_required().unwrap();
+///     embed_resource::link_dependency_resources(&["company-branding"], &[LinkTarget::AllBins]).manifest_optional().unwrap();
+/// }
+/// ```
+pub fn link_dependency_resources<S: AsRef<str>>(links: &[S], link_targets: &[LinkTarget]) -> CompilationResult {
+    let target = try_compile_impl!(env_target());
+    link_exported_resources(&target,
+                            links.iter().map(|links| {
+                                let links = links.as_ref();
+                                (links, env::var_os(format!("DEP_{}_RESOURCE", links.to_uppercase().replace('-', "_"))))
+                            }),
+                            link_targets)
+}
+
+/// [`link_dependency_resources()`], with what each dependency exported (its `links` key and `$DEP_{links}_RESOURCE`, if set)
+fn link_exported_resources<'l, E: IntoIterator<Item = (&'l str, Option<OsString>)>>(target: &str, exported: E, link_targets: &[LinkTarget])
+                                                                                   -> CompilationResult {
+    if !target.contains("-windows-") {
+        return CompilationResult::NotWindows;
+    }
+    try_compile_impl!(check_link_targets(link_targets));
+
+    let mut files = vec![];
+    for (links, exported) in exported {
+        match exported {
+            Some(exported) => files.extend(env::split_paths(&exported)),
+            None => return CompilationResult::NotAttempted(ResourceError::MissingDependency(links.to_string())),
+        }
+    }
+
+    // Only what'd end up in the same artifacts could conflict
+    let own = directives::linked_to(link_targets).into_iter().map(PathBuf::from).collect::<Vec<_>>();
+    let mut manifests = BTreeMap::<_, &PathBuf>::new();
+    for file in own.iter().chain(&files) {
+        for id in try_compile_impl!(manifest_ids(file).map_err(CompilationResult::Failed)) {
+            match manifests.get(&id) {
+                Some(&first) if first != file => {
+                    return CompilationResult::Failed(ResourceError::ConflictingManifests {
+                        id: id.to_string(),
+                        first: first.clone(),
+                        second: file.clone(),
+                    })
+                }
+                _ => {
+                    manifests.insert(id, file);
+                }
+            }
+        }
+    }
+
+    files.sort();
+    files.dedup();
+    for file in files {
+        let out_file = try_compile_impl!(file.to_str().ok_or_else(|| CompilationResult::Failed(ResourceError::InvalidPath(file.clone()))));
+        let prefix = file.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+        for link_target in link_targets {
+            try_compile_impl!(directives::link(link_target, target, prefix, out_file).map_err(CompilationResult::Failed));
+        }
+    }
+    CompilationResult::Ok
+}
+
+/// The IDs of the application manifests in `file`, a `.res` or a COFF object
+fn manifest_ids(file: &Path) -> Result<Vec<res::ResId>, ResourceError> {
+    let data = fs::read(file).map_err(|e| ResourceError::io(file, e))?;
+    let ids = if data.starts_with(&[0, 0, 0, 0, 0x20, 0, 0, 0]) {
+        res::read(&data).map_err(ResourceError::InvalidResource)?.into_iter().map(|e| (e.tp, e.name)).collect::<Vec<_>>()
+    } else {
+        coff::read_ids(&data).map_err(ResourceError::InvalidResource)?.into_iter().map(|(tp, name, _)| (tp, name)).collect()
+    };
+    Ok(ids.into_iter().filter(|(tp, _)| *tp == res::ResId::Ordinal(24)).map(|(_, name)| name).collect())
+}
+
+fn compile_and_link<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(
+    resource_file: &Path, generated: Option<&Generated>, parameters: ParameterBundle<Ms, Mi, Is, Ii>, link_targets: &[LinkTarget],
+    compilers: &[CompilerPreference])
+    -> CompilationResult {
+    let target = try_compile_impl!(env_target());
+    // Before compiling, like in link_dependency_resources(), so bad link targets fail even without a resource compiler
+    if target.contains("-windows-") {
+        try_compile_impl!(check_link_targets(link_targets));
+    }
+    let (prefix, _, out_file) = try_compile_impl!(compile_impl(&target, resource_file, generated, parameters, None, compilers));
+    for link_target in link_targets {
+        try_compile_impl!(directives::link(link_target, &target, &prefix, &out_file).map_err(CompilationResult::Failed));
+    }
+    CompilationResult::Ok
+}
+
+/// [`LinkTarget::Dependents`] needs a `links` key, and [`LinkTarget::Bins`] binaries that exist
+fn check_link_targets(link_targets: &[LinkTarget]) -> Result<(), CompilationResult> {
+    if link_targets.contains(&LinkTarget::Dependents) && env::var_os("CARGO_MANIFEST_LINKS").is_none() {
+        return Err(CompilationResult::Failed(ResourceError::MissingEnv("CARGO_MANIFEST_LINKS")));
+    }
+    for target in link_targets {
+        if let LinkTarget::Bins(names) = target {
+            let bins = targets::binaries().map_err(CompilationResult::Failed)?;
+            if let Some(name) = names.iter().find(|n| !bins.contains(n)) {
+                return Err(CompilationResult::Failed(ResourceError::UnknownBinary {
+                    name: name.clone(),
+                    bins,
+                }));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Everything that goes into a compilation in [`compile_impl()`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CompilationKey {
+    target: String,
+    resource_file: PathBuf,
+    generated: Option<Generated>,
+    macros: Vec<OsString>,
+    include_dirs: Vec<OsString>,
+    out_dir: String,
+    compiler: Option<(CompilerKind, OsString)>,
+    /// Whether no `cargo:` directives were printed
+    outside_cargo: bool,
+}
+
+/// What [`compile_impl()`] already made in this run, and its result, so compiling the same thing again
+/// (like with [`compile()`] then [`compile_for()`]) just hands back the same artifact
+static COMPILED: Mutex<Vec<(CompilationKey, Compiled)>> = Mutex::new(vec![]);
+
+/// The prefix, `$OUT_DIR`, and output of a compilation
+type Compiled = (String, String, String);
+
+/// Compile `resource_file` for `target`; if `generated` is specified, it's written to `$OUT_DIR/resource_file`
+/// (and its files next to it) first, and that's compiled instead
+///
+/// `out_dir` overrides `$OUT_DIR`; `compilers`, if not empty, override the usual choice, cf. [`Resource::compilers()`]
+///
+/// Compiling the same thing with the same compiler twice only runs the compiler once, cf. [`COMPILED`];
+/// neither does compiling it again in a later run if none of the files it depends on changed, cf. `cache`
 fn compile_impl<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>, P: Into<ParameterBundle<Ms, Mi, Is, Ii>>>(
-    resource_file: &Path, parameters: P)
-    -> Result<(&str, String, String), CompilationResult> {
-    let mut comp = ResourceCompiler::new();
+    target: &str, resource_file: &Path, generated: Option<&Generated>, parameters: P, out_dir: Option<&Path>, compilers: &[CompilerPreference])
+    -> Result<Compiled, CompilationResult> {
+    let mut comp = ResourceCompiler::new(target, compilers);
+
+    // Kept for dependency discovery
+    let parameters = parameters.into();
+    let macros = parameters.macros.into_iter().map(|m| m.as_ref().to_os_string()).collect::<Vec<_>>();
+    let include_dirs = parameters.include_dirs.into_iter().map(|id| id.as_ref().to_os_string()).collect::<Vec<_>>();
+    let hash = Fnv1a::hash((resource_file, generated, &macros, &include_dirs));
+    let invalid_path = || CompilationResult::Failed(ResourceError::InvalidPath(resource_file.to_path_buf()));
+    let prefix = || resource_file.file_stem().and_then(OsStr::to_str).map(|stem| output_prefix(stem, hash)).ok_or_else(invalid_path);
+
     if let Some(missing) = comp.is_supported() {
-        if missing.is_empty() {
-            Err(CompilationResult::NotWindows)
-        } else {
-            Err(CompilationResult::NotAttempted(missing))
+        match missing {
+            None => Err(CompilationResult::NotWindows),
+            Some(missing) => {
+                deps::rerun_if_env_changed(target);
+                // Still whatever the last compilation depended on (if any), since changing those could be what fixes it
+                if let (Ok(out_dir), Ok(prefix)) = (out_dir_or_env(out_dir), prefix()) {
+                    for dep in generated.iter().flat_map(|g| &g.deps) {
+                        cargo_directive!("rerun-if-changed={}", dep.display());
+                    }
+                    let resource_file = if generated.is_some() { Path::new(&out_dir).join(resource_file) } else { resource_file.to_path_buf() };
+                    deps::rerun_if_changed(&resource_file, &cache::recorded_deps(&out_dir, &prefix), &out_dir);
+                }
+                Err(CompilationResult::NotAttempted(missing))
+            }
         }
     } else {
-        let prefix = &resource_file.file_stem().expect("resource_file has no stem").to_str().expect("resource_file's stem not UTF-8");
-        let out_dir = env::var("OUT_DIR").expect("No OUT_DIR env var");
+        deps::rerun_if_env_changed(target);
+        let out_dir = out_dir_or_env(out_dir)?;
+
+        let key = CompilationKey {
+            target: target.to_string(),
+            resource_file: resource_file.to_path_buf(),
+            generated: generated.cloned(),
+            macros: macros.clone(),
+            include_dirs: include_dirs.clone(),
+            out_dir: out_dir.clone(),
+            compiler: comp.chosen().map(|(kind, exe)| (kind, exe.to_os_string())),
+            outside_cargo: OUTSIDE_CARGO.with(Cell::get),
+        };
+        if let Some((_, compiled)) = COMPILED.lock().unwrap_or_else(PoisonError::into_inner).iter().find(|(k, _)| *k == key) {
+            return Ok(compiled.clone());
+        }
 
-        let out_file = comp.compile_resource(&out_dir, &prefix, resource_file.to_str().expect("resource_file not UTF-8"), parameters.into())
-            .map_err(CompilationResult::Failed)?;
-        Ok((prefix, out_dir, out_file))
+        let generated_file;
+        let resource_file = match generated {
+            Some(generated) => {
+                generated_file = Path::new(&out_dir).join(resource_file);
+                let write = |path: &Path, data: &[u8]| fs::write(path, data).map_err(|e| CompilationResult::Failed(ResourceError::io(path, e)));
+                for (name, data) in &generated.files {
+                    write(&Path::new(&out_dir).join(name), data)?;
+                }
+                write(&generated_file, generated.rc.as_bytes())?;
+                generated_file.as_path()
+            }
+            None => resource_file,
+        };
+        let prefix = prefix()?;
+        let resource_file_str = resource_file.to_str().ok_or_else(|| CompilationResult::Failed(ResourceError::InvalidPath(resource_file.to_path_buf())))?;
+
+        let inputs = cache::inputs(target, key.compiler.as_ref(), hash, |var| env::var_os(var));
+        let (out_file, deps) = match cache::lookup(&out_dir, &prefix, inputs) {
+            Some(cached) => cached,
+            None => {
+                for stale in [".d", "-preprocessed.rc", "-preprocessed.d"] {
+                    let _ = fs::remove_file(Path::new(&out_dir).join(format!("{}{}", prefix, stale)));
+                }
+                let fallback = comp.is_fallback();
+                let out_file = comp.compile_resource(&out_dir,
+                                      &prefix,
+                                      resource_file_str,
+                                      ParameterBundle {
+                                          macros: &macros,
+                                          include_dirs: &include_dirs,
+                                      })
+                    .map_err(|err| {
+                        let err = compile_failed(err, fallback);
+                        if let CompilationResult::NotAttempted(_) = err {
+                            // Like when there's no compiler at all
+                            for dep in generated.iter().flat_map(|g| &g.deps) {
+                                cargo_directive!("rerun-if-changed={}", dep.display());
+                            }
+                            deps::rerun_if_changed(resource_file, &[], &out_dir);
+                        }
+                        err
+                    })?;
+                let deps = deps::find(target, resource_file, &out_dir, &prefix, &macros, &include_dirs);
+                cache::store(&out_dir, &prefix, inputs, &out_file, &deps);
+                (out_file, deps)
+            }
+        };
+        for dep in generated.iter().flat_map(|g| &g.deps) {
+            cargo_directive!("rerun-if-changed={}", dep.display());
+        }
+        deps::rerun_if_changed(resource_file, &deps, &out_dir);
+
+        let compiled = (prefix, out_dir, out_file);
+        COMPILED.lock().unwrap_or_else(PoisonError::into_inner).push((key, compiled.clone()));
+        Ok(compiled)
     }
 }
 
+/// What `compile_resource()` failing with `err` means for the compilation
+///
+/// When the built-in compiler's only used because no other was found, what it doesn't support (or can't parse)
+/// is for lack of a resource compiler, not the resource's fault.
+fn compile_failed(err: ResourceError, fallback: bool) -> CompilationResult {
+    match err {
+        ResourceError::InvalidResource(_) if fallback => CompilationResult::NotAttempted(err),
+        err => CompilationResult::Failed(err),
+    }
+}
+
+/// Write `res()`, the contents of a `.res` file, to where [`compile_impl()`] would've compiled a resource called `name`,
+/// converting it to a COFF object if not targeting MSVC
+fn res_impl<R: AsRef<[u8]>, F: FnOnce() -> Result<R, ResourceError>>(target: &str, name: &Path, res: F, out_dir: Option<&Path>)
+                                                                     -> Result<Compiled, CompilationResult> {
+    if !target.contains("-windows-") {
+        return Err(CompilationResult::NotWindows);
+    }
+    let out_dir = out_dir_or_env(out_dir)?;
+    let stem = name.file_stem().and_then(OsStr::to_str).ok_or_else(|| CompilationResult::Failed(ResourceError::InvalidPath(name.to_path_buf())))?;
+    let res = res().map_err(CompilationResult::Failed)?;
+    let res = res.as_ref();
+    let prefix = output_prefix(stem, Fnv1a::hash(res));
+
+    let entries = res::read(res).map_err(|e| CompilationResult::Failed(ResourceError::InvalidResource(e)))?;
+    let (out_file, data) = if target.ends_with("-msvc") {
+        (Path::new(&out_dir).join(format!("{}.res", prefix)), res.to_vec())
+    } else {
+        (Path::new(&out_dir).join(format!("{}.o", prefix)),
+         coff::write(&entries, coff_machine(target).map_err(CompilationResult::Failed)?)
+             .map_err(|e| CompilationResult::Failed(ResourceError::InvalidResource(e)))?)
+    };
+    fs::write(&out_file, data).map_err(|e| CompilationResult::Failed(ResourceError::io(&out_file, e)))?;
+    let out_file = out_file.into_os_string().into_string().map_err(|f| CompilationResult::Failed(ResourceError::InvalidPath(f.into())))?;
+    Ok((prefix, out_dir, out_file))
+}
+
+/// Whether `exe` is `zig`, going by what `exe rc /?` says
+fn is_zig_rc(exe: &OsStr) -> io::Result<bool> {
+    let help = Command::new(exe).args(["rc", "/?"]).output()?.stdout;
+    Ok(help.windows(b"zig rc".len()).any(|w| w == b"zig rc"))
+}
+
+/// Compile `resource` into `out_file` with the built-in compiler, writing its dependencies to `{out_dir}/{prefix}.d`
+fn compile_builtin<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(
+    target: &str, out_dir: &str, prefix: &str, out_file: &str, resource: &str, parameters: ParameterBundle<Ms, Mi, Is, Ii>)
+    -> Result<(), ResourceError> {
+    let macros = parameters.macros
+        .into_iter()
+        .map(|m| m.as_ref().to_str().map(str::to_string).ok_or_else(|| ResourceError::InvalidMacro(m.as_ref().to_os_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let include_dirs = Some(PathBuf::from(out_dir)).into_iter().chain(parameters.include_dirs.into_iter().map(|id| PathBuf::from(id.as_ref()))).collect();
+    let (entries, dependencies) = builtin::compile(Path::new(resource), macros.iter().map(String::as_str), include_dirs)
+        .map_err(ResourceError::InvalidResource)?;
+
+    write_entries(target, out_file, &entries)?;
+
+    let depfile = format!("{}/{}.d", out_dir, prefix);
+    fs::write(&depfile, deps::write_depfile(out_file, dependencies.iter().map(PathBuf::as_path)))
+        .map_err(|e| ResourceError::io(Path::new(&depfile), e))
+}
+
+/// Write a `.res` file for MSVC targets, and a COFF object for everything else
+fn write_entries(target: &str, out_file: &str, entries: &[res::ResEntry]) -> Result<(), ResourceError> {
+    let data = if target.ends_with("-msvc") {
+        res::write(entries)
+    } else {
+        coff::write(entries, coff_machine(target)?).map_err(ResourceError::InvalidResource)?
+    };
+    fs::write(out_file, data).map_err(|e| ResourceError::io(Path::new(out_file), e))
+}
+
+/// Wrap `out_file` in a static library in `out_dir`, `{prefix}.lib` for MSVC targets and `lib{prefix}.a` otherwise,
+/// for linking with `cargo:rustc-link-lib=static:+whole-archive={prefix}`; only written where that's how it'll be linked
+///
+/// `.res` files can't be archive members, so for MSVC targets they're converted to COFF objects, like `cvtres.exe` would
+fn write_archive(target: &str, out_dir: &str, prefix: &str, out_file: &str) -> Result<(), ResourceError> {
+    let data = fs::read(out_file).map_err(|e| ResourceError::io(Path::new(out_file), e))?;
+    let flavour = archive::Flavour::from_target(target);
+    let (archive_file, member, data) = match flavour {
+        archive::Flavour::Msvc => {
+            let entries = res::read(&data).map_err(ResourceError::InvalidResource)?;
+            (format!("{}.lib", prefix), format!("{}.obj", prefix), coff::write(&entries, coff_machine(target)?).map_err(ResourceError::InvalidResource)?)
+        }
+        archive::Flavour::Gnu => (format!("lib{}.a", prefix), format!("{}.o", prefix), data),
+    };
+
+    let archive_file = Path::new(out_dir).join(archive_file);
+    let member = archive::Member {
+        name: member,
+        data: &data,
+        symbols: vec![],
+    };
+    fs::write(&archive_file, archive::write(&[member], flavour)).map_err(|e| ResourceError::io(&archive_file, e))
+}
+
+/// The COFF machine for `target`, cf. [`coff::Machine::from_target()`]
+fn coff_machine(target: &str) -> Result<coff::Machine, ResourceError> {
+    coff::Machine::from_target(target).ok_or_else(|| ResourceError::InvalidResource(format!("Unsupported target architecture: {}", target).into()))
+}
+
+/// `out_dir` or `$OUT_DIR`
+fn out_dir_or_env(out_dir: Option<&Path>) -> Result<String, CompilationResult> {
+    match out_dir {
+        Some(out_dir) => Ok(out_dir.to_str().ok_or_else(|| CompilationResult::Failed(ResourceError::InvalidPath(out_dir.to_path_buf())))?.to_string()),
+        None => env::var("OUT_DIR").map_err(|_| CompilationResult::Failed(ResourceError::MissingEnv("OUT_DIR"))),
+    }
+}
+
+/// `{stem}-{hash}`: resources with the same name (in different directories, or with different parameters or contents)
+/// mustn't overwrite each other
+fn output_prefix(stem: &str, hash: u64) -> String {
+    format!("{}-{:08x}", stem, (hash ^ (hash >> 32)) as u32)
+}
+
+/// A `cc::Build` for preprocessing resource scripts, which emits no `cargo:` directives of its own, save for warnings in build scripts
+fn cc_build(target: &str) -> cc::Build {
+    let mut ret = cc::Build::new();
+    ret.target(target).cargo_metadata(false).cargo_warnings(!OUTSIDE_CARGO.with(Cell::get)).define("RC_INVOKED", None);
+    ret
+}
+
+/// Run `resource_file` through the C preprocessor for `target`, optionally writing a Make-style `depfile` of what it included
+fn preprocess(target: &str, resource_file: &Path, macros: &[OsString], include_dirs: &[OsString], depfile: Option<&Path>)
+              -> Result<Vec<u8>, ResourceError> {
+    let mut build = cc_build(target);
+    for m in macros {
+        let (name, value) = split_macro(m)?;
+        build.define(name, value);
+    }
+    for id in include_dirs {
+        build.include(id);
+    }
+    let preprocess_failed = |e| ResourceError::preprocess_failed(resource_file, e);
+    if build.try_get_compiler().map_err(preprocess_failed)?.is_like_msvc() {
+        build.flag("/TC");
+    } else {
+        build.flag("-xc");
+        if let Some(depfile) = depfile {
+            build.flag("-MMD").flag("-MF").flag(depfile.as_os_str());
+        }
+    }
+    build.file(resource_file).try_expand().map_err(preprocess_failed)
+}
+
+/// `NAME` or `NAME=VALUE`, for compilers that take macros as strings
+fn split_macro(m: &OsStr) -> Result<(&str, Option<&str>), ResourceError> {
+    let mut m = m.to_str().ok_or_else(|| ResourceError::InvalidMacro(m.to_os_string()))?.splitn(2, '=');
+    Ok((m.next().unwrap(), m.next()))
+}
+
 fn apply_parameters<'t, Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(to: &'t mut Command, macro_pref: &str,
                                                                                                                       include_dir_pref: &str,
                                                                                                                       parameters: ParameterBundle<Ms,
@@ -621,23 +1307,36 @@ pub fn find_windows_sdk_tool<T: AsRef<str>>(tool: T) -> Option<PathBuf> {
 }
 
 
+/// `$TARGET`, or, if Cargo didn't set it, [`CompilationResult::NotAttempted`]
+fn env_target() -> Result<String, CompilationResult> {
+    env_target_and_rc().map(|(target, _)| target).map_err(CompilationResult::NotAttempted)
+}
+
 #[allow(unused)]
-fn env_target_and_rc() -> Result<(String, Option<OsString>), Cow<'static, str>> {
-    let target = env::var("TARGET").map_err(|_| Cow::from("no $TARGET"))?;
-    let rc = env::var_os(&format!("RC_{}", target)).or_else(|| env::var_os(&format!("RC_{}", target.replace('-', "_")))).or_else(|| env::var_os("RC"));
+fn env_target_and_rc() -> Result<(String, Option<OsString>), ResourceError> {
+    let target = env::var("TARGET").map_err(|_| ResourceError::MissingEnv("TARGET"))?;
+    let rc = env_for_target("RC", &target).map(|(_, rc)| rc);
     Ok((target, rc))
 }
 
+/// The first of `${var}_$TARGET`, `${var}_${TARGET//-/_}`, and `$var` that's set, and its name
+fn env_for_target(var: &str, target: &str) -> Option<(String, OsString)> {
+    [format!("{}_{}", var, target), format!("{}_{}", var, target.replace('-', "_")), var.to_string()]
+        .into_iter()
+        .find_map(|var| env::var_os(&var).map(|val| (var, val)))
+}
+
 
 #[cfg(any(not(target_os = "windows"), all(target_os = "windows", not(target_env = "msvc"))))]
 mod windres {
-    use self::super::{ParameterBundle, apply_parameters};
+    use self::super::{CompilerPreference, ToolchainCandidate, ParameterBundle, ResourceError, CompilerKind, apply_parameters, env_for_target, diagnostics,
+                      cc_build, compile_builtin, write_entries, split_macro, is_zig_rc, error, res};
     use std::process::{Command, Stdio};
+    use std::path::{PathBuf, Path};
     use std::ffi::{OsString, OsStr};
+    use std::{env, iter, fs, io};
     use std::borrow::Cow;
-    use std::path::Path;
     use memchr::memmem;
-    use std::fs;
 
     #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
     pub enum CompilerType {
@@ -647,6 +1346,23 @@ mod windres {
         LlvmRc { has_no_preprocess: bool, },
         /// MinGW windres
         WindRes,
+        /// `zig rc`, which takes `RC.EXE` arguments and preprocesses with its own headers
+        ZigRc,
+        /// The built-in compiler, `fallback` if only used because no external one is available
+        ///
+        /// Produces a `.res` file for MSVC targets and a COFF object otherwise
+        Builtin { fallback: bool, },
+    }
+
+    impl CompilerType {
+        pub fn kind(self) -> CompilerKind {
+            match self {
+                CompilerType::LlvmRc { .. } => CompilerKind::LlvmRc,
+                CompilerType::WindRes => CompilerKind::WindRes,
+                CompilerType::ZigRc => CompilerKind::ZigRc,
+                CompilerType::Builtin { .. } => CompilerKind::Builtin,
+            }
+        }
     }
 
     #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -659,7 +1375,7 @@ mod windres {
     fn is_runnable(s: &str) -> bool {
         Command::new(s).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn().map(|mut c| c.kill()).is_ok()
     }
-    fn if_runnable(executable: Cow<'static, str>, maketp: fn(&str) -> CompilerType) -> Result<Compiler, Cow<'static, str>> {
+    fn if_runnable(executable: Cow<'static, str>, maketp: fn(&str) -> CompilerType) -> Result<Compiler, ResourceError> {
         if is_runnable(&executable) {
             Ok(Compiler {
                 tp: maketp(&executable),
@@ -669,11 +1385,11 @@ mod windres {
                 },
             })
         } else {
-            Err(executable)
+            Err(ResourceError::CompilerNotFound { tried: vec![PathBuf::from(&*executable)] })
         }
     }
     impl Compiler {
-        pub fn llvm_rc(executable: Cow<'static, str>) -> Result<Compiler, Cow<'static, str>> {
+        pub fn llvm_rc(executable: Cow<'static, str>) -> Result<Compiler, ResourceError> {
             if_runnable(executable, |executable| {
                 CompilerType::LlvmRc {
                     has_no_preprocess: Command::new(executable)
@@ -685,27 +1401,124 @@ mod windres {
                 }
             })
         }
-        pub fn windres(executable: Cow<'static, str>) -> Result<Compiler, Cow<'static, str>> {
+        pub fn windres(executable: Cow<'static, str>) -> Result<Compiler, ResourceError> {
             if_runnable(executable, |_| CompilerType::WindRes)
         }
+        pub fn zig_rc(executable: Cow<'static, str>) -> Result<Compiler, ResourceError> {
+            if_runnable(executable, |_| CompilerType::ZigRc)
+        }
+        pub fn builtin(fallback: bool) -> Compiler {
+            Compiler {
+                tp: CompilerType::Builtin { fallback },
+                executable: Cow::Borrowed(OsStr::new("embed-resource")),
+            }
+        }
+
+        /// Record `compiler`, considered because of `source`, in `candidates`; `executable` and `kind` describe it if it's `Err`
+        pub fn consider(candidates: &mut Vec<ToolchainCandidate>, source: &str, executable: &OsStr, kind: Option<CompilerKind>,
+                        compiler: Result<Compiler, ResourceError>)
+                        -> Result<Compiler, ResourceError> {
+            candidates.push(match compiler.as_ref() {
+                Ok(compiler) => {
+                    let mut cand = ToolchainCandidate::new(source, &*compiler.executable, Some(compiler.tp.kind()), true);
+                    if let CompilerType::LlvmRc { has_no_preprocess: true } = compiler.tp {
+                        cand.capabilities.push("no-preprocess".to_string());
+                    }
+                    cand
+                }
+                Err(err) => {
+                    let mut cand = ToolchainCandidate::new(source, executable, kind, matches!(err, ResourceError::UnknownCompiler(_)));
+                    cand.error = Some(err.to_string());
+                    cand
+                }
+            });
+            compiler
+        }
+
+        /// Try `compilers`, from `source`, in order, recording each in `candidates`; the first that runs wins
+        pub fn prefer(compilers: &[CompilerPreference], source: &str, candidates: &mut Vec<ToolchainCandidate>) -> Result<Compiler, ResourceError> {
+            let mut errors = vec![];
+            for pref in compilers {
+                let compiler = match pref {
+                    CompilerPreference::LlvmRc(exe) => Compiler::llvm_rc(exe.clone()),
+                    CompilerPreference::WindRes(exe) => Compiler::windres(exe.clone()),
+                    CompilerPreference::ZigRc(exe) => Compiler::zig_rc(exe.clone()),
+                    CompilerPreference::Builtin => Ok(Compiler::builtin(false)),
+                    CompilerPreference::RcExe(exe) => {
+                        Err(ResourceError::UnsupportedCompiler {
+                            exe: PathBuf::from(&**exe),
+                            kind: CompilerKind::RcExe,
+                        })
+                    }
+                };
+                match Compiler::consider(candidates, source, OsStr::new(pref.executable()), Some(pref.kind()), compiler) {
+                    Ok(compiler) => return Ok(compiler),
+                    Err(err) => errors.push(err),
+                }
+            }
+            Err(ResourceError::NoUsableCompiler(errors))
+        }
+
+        /// Try `executables` (`kind` LLVM-RC or windres), with why they're considered, in order, recording each in `candidates`;
+        /// the first that runs wins
+        pub fn discover<E: IntoIterator<Item = (&'static str, String)>>(candidates: &mut Vec<ToolchainCandidate>, kind: CompilerKind, executables: E)
+                                                                         -> Result<Compiler, ResourceError> {
+            let mut tried = vec![];
+            for (source, exe) in executables {
+                let compiler = match kind {
+                    CompilerKind::LlvmRc => Compiler::llvm_rc(exe.clone().into()),
+                    _ => Compiler::windres(exe.clone().into()),
+                };
+                match Compiler::consider(candidates, source, OsStr::new(&exe), Some(kind), compiler) {
+                    Ok(compiler) => return Ok(compiler),
+                    Err(_) => tried.push(PathBuf::from(exe)),
+                }
+            }
+            Err(ResourceError::CompilerNotFound { tried })
+        }
 
+        /// The compiler `$RC_$TARGET`, `$RC_${TARGET//-/_}`, or `$RC` names, if any, recorded in `candidates`
+        pub fn from_env(target: &str, candidates: &mut Vec<ToolchainCandidate>) -> Option<Result<Compiler, ResourceError>> {
+            let (var, rc) = env_for_target("RC", target)?;
+            let source = format!("${}", var);
+            Some(if rc == "embed-resource" {
+                Compiler::consider(candidates, &source, &rc, Some(CompilerKind::Builtin), Ok(Compiler::builtin(false)))
+            } else {
+                Compiler::consider(candidates, &source, &rc, None, guess_compiler_variant(rc.clone()))
+            })
+        }
+
+        #[allow(clippy::too_many_arguments)]
         pub fn compile<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>, Wp: FnOnce(&mut Command) -> &mut Command>(
-            &self, out_dir: &str, prefix: &str, out_file: String, resource: &str, parameters: ParameterBundle<Ms, Mi, Is, Ii>, fo: &str, c: &str,
-            no_preprocess: &str, windres_params: Wp)
-            -> Result<String, Cow<'static, str>> {
+            &self, target: &str, out_dir: &str, prefix: &str, out_file: String, resource: &str, parameters: ParameterBundle<Ms, Mi, Is, Ii>, fo: &str,
+            c: &str, no_preprocess: &str, windres_params: Wp)
+            -> Result<String, ResourceError> {
             match self.tp {
                 CompilerType::LlvmRc { has_no_preprocess } => {
+                    // Both left for deps::find()
                     let preprocessed_path = format!("{}/{}-preprocessed.rc", out_dir, prefix);
-                    fs::write(&preprocessed_path,
-                              cc_xc(apply_parameters_cc(cc::Build::new().define("RC_INVOKED", None), parameters))
-                                  .file(resource)
-                                  .cargo_metadata(false)
-                                  .include(out_dir)
-                                  .expand()).map_err(|e| e.to_string())?;
-
-                    try_command(Command::new(&*self.executable)
-                                .args(&[fo, &out_file])
-                                .args(&[c, "65001"]) // UTF-8, cf. https://github.com/nabijaczleweli/rust-embed-resource/pull/73
+                    let depfile = format!("{}/{}-preprocessed.d", out_dir, prefix);
+                    // Also where llvm-rc looks for files resources reference
+                    let include_dirs = parameters.include_dirs.into_iter().map(|id| id.as_ref().to_os_string()).collect::<Vec<_>>();
+                    let parameters = ParameterBundle {
+                        macros: parameters.macros,
+                        include_dirs: &include_dirs,
+                    };
+                    let mut build = cc_build(target);
+                    cc_xc(apply_parameters_cc(&mut build, parameters)?);
+                    if !build.get_compiler().is_like_msvc() {
+                        build.flag("-MMD").flag("-MF").flag(&depfile);
+                    }
+                    let preprocessed = build.file(resource)
+                        .include(out_dir)
+                        .try_expand()
+                        .map_err(|e| ResourceError::preprocess_failed(Path::new(resource), e))?;
+                    fs::write(&preprocessed_path, &preprocessed).map_err(|e| ResourceError::io(Path::new(&preprocessed_path), e))?;
+                    let preprocessed = String::from_utf8_lossy(&preprocessed);
+
+                    error::run(Command::new(&*self.executable)
+                                .args([fo, &out_file])
+                                .args([c, "65001"]) // UTF-8, cf. https://github.com/nabijaczleweli/rust-embed-resource/pull/73
                                 .args(if has_no_preprocess {
                                     // We already preprocessed using CC. llvm-rc preprocessing
                                     // requires having clang in PATH, which more exotic toolchains
@@ -714,45 +1527,146 @@ mod windres {
                                 } else {
                                     None
                                 })
-                                .args(&["--", &preprocessed_path])
+                                .args(include_dirs.iter().flat_map(|id| [OsStr::new("/I"), id]))
+                                .args(["--", &preprocessed_path])
                                 .stdin(Stdio::piped())
                                 .current_dir(or_curdir(Path::new(resource).parent().expect("Resource parent nonexistent?"))),
-                                Path::new(&self.executable),
-                                "compile",
-                                &preprocessed_path,
-                                &out_file)?;
+                               Path::new(&self.executable),
+                               Some(&diagnostics::Locator::new(Path::new(&preprocessed_path), &preprocessed)))?;
+                    res_to_object(target, &out_file)?;
+                }
+                CompilerType::ZigRc => {
+                    error::run(apply_parameters(Command::new(&*self.executable).args(["rc", "/fo", &out_file, "/c65001", "/i", out_dir]),
+                                                "/d",
+                                                "/i",
+                                                parameters)
+                                   .args(["--", resource]),
+                               Path::new(&self.executable),
+                               None)?;
+                    res_to_object(target, &out_file)?;
                 }
                 CompilerType::WindRes => {
-                    try_command(apply_parameters(windres_params(Command::new(&*self.executable)
-                                                     .args(&["--input", resource, "--output", &out_file, "--include-dir", out_dir, "--output-format=coff"])),
-                                                 "-D",
-                                                 "-I",
-                                                 parameters),
-                                Path::new(&self.executable),
-                                "compile",
-                                resource,
-                                &out_file)?;
+                    error::run(apply_parameters(windres_params(Command::new(&*self.executable)
+                                                    .args(["--input", resource, "--output", &out_file, "--include-dir", out_dir, "--output-format=coff"])),
+                                                "-D",
+                                                "-I",
+                                                parameters),
+                               Path::new(&self.executable),
+                               None)?;
                 }
+                CompilerType::Builtin { .. } => compile_builtin(target, out_dir, prefix, &out_file, resource, parameters)?,
             }
             Ok(out_file)
         }
     }
 
 
-    fn apply_parameters_cc<'t, Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(to: &'t mut cc::Build,
+    /// `tool` as the default choice, resolved on `$PATH`, if it's there
+    pub fn default_tool(tool: String) -> (&'static str, String) {
+        ("default", on_path(&tool).unwrap_or(tool))
+    }
+
+    /// LLVM's `tool` on `$PATH`, then suffixed with a version (like Debian's `llvm-rc-18`, highest first),
+    /// then in `llvm-config --bindir`, and why they're considered
+    pub fn llvm_tool(tool: &'static str) -> impl Iterator<Item = (&'static str, String)> {
+        iter::once(default_tool(tool.to_string()))
+            .chain(iter::once_with(move || versioned_on_path(tool, &env::var_os("PATH").unwrap_or_default())).flatten().map(|exe| ("$PATH, versioned", exe)))
+            .chain(iter::once_with(move || llvm_config_bindir(tool)).flatten().map(|exe| ("llvm-config --bindir", exe)))
+    }
+
+    /// `tool` in the first `$PATH` directory that has it
+    fn on_path(tool: &str) -> Option<String> {
+        env::split_paths(&env::var_os("PATH")?)
+            .map(|dir| dir.join(format!("{}{}", tool, env::consts::EXE_SUFFIX)))
+            .find(|exe| exe.is_file())
+            .and_then(|exe| exe.into_os_string().into_string().ok())
+    }
+
+    /// `tool-N` in `path` (like `$PATH`), highest `N` first, each from the first directory that has it
+    fn versioned_on_path(tool: &str, path: &OsStr) -> Vec<String> {
+        let mut found: Vec<(u32, PathBuf)> = vec![];
+        for dir in env::split_paths(path) {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let name = entry.file_name();
+                let version = name.to_str()
+                    .and_then(|n| n.strip_suffix(env::consts::EXE_SUFFIX))
+                    .and_then(|n| n.strip_prefix(tool))
+                    .and_then(|n| n.strip_prefix('-'))
+                    .and_then(|v| v.parse().ok());
+                if let Some(version) = version {
+                    if !found.iter().any(|(v, _)| *v == version) && dir.join(&name).is_file() {
+                        found.push((version, dir.join(name)));
+                    }
+                }
+            }
+        }
+        found.sort_by(|(lv, _), (rv, _)| rv.cmp(lv));
+        found.into_iter().filter_map(|(_, exe)| exe.into_os_string().into_string().ok()).collect()
+    }
+
+    fn llvm_config_bindir(tool: &str) -> Option<String> {
+        let out = Command::new("llvm-config").arg("--bindir").stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+        if !out.status.success() {
+            return None;
+        }
+        let exe = Path::new(String::from_utf8(out.stdout).ok()?.trim()).join(format!("{}{}", tool, env::consts::EXE_SUFFIX));
+        if exe.is_file() {
+            exe.into_os_string().into_string().ok()
+        } else {
+            None
+        }
+    }
+
+    /// LLVM-RC and `zig rc` only make `.res` files, but GNU linkers want objects
+    fn res_to_object(target: &str, out_file: &str) -> Result<(), ResourceError> {
+        if !target.ends_with("-msvc") {
+            let entries = res::read(&fs::read(out_file).map_err(|e| ResourceError::io(Path::new(out_file), e))?).map_err(ResourceError::InvalidResource)?;
+            write_entries(target, out_file, &entries)?;
+        }
+        Ok(())
+    }
+
+    /// -V will print the version in windres.
+    /// /? will print the help in LLVM-RC and Microsoft RC.EXE.
+    /// If combined, /? takes precedence over -V.
+    /// zig needs rc /? for its help.
+    pub fn guess_compiler_variant(s: OsString) -> Result<Compiler, ResourceError> {
+        let not_found = |err: io::Error| if err.kind() == io::ErrorKind::NotFound {
+            ResourceError::CompilerNotFound { tried: vec![PathBuf::from(&s)] }
+        } else {
+            ResourceError::io(Path::new(&s), err)
+        };
+
+        let out = Command::new(&s).args(["-V", "/?"]).output().map_err(not_found)?;
+        let tp = if out.stdout.starts_with(b"GNU windres") || out.stdout.starts_with(b"llvm-windres") {
+            CompilerType::WindRes
+        } else if out.stdout.starts_with(b"OVERVIEW: Resource Converter") || out.stdout.starts_with(b"OVERVIEW: LLVM Resource Converter") {
+            CompilerType::LlvmRc { has_no_preprocess: memmem::find(&out.stdout, b"no-preprocess").is_some() }
+        } else if is_zig_rc(&s).map_err(not_found)? {
+            CompilerType::ZigRc
+        } else {
+            return Err(ResourceError::UnknownCompiler(PathBuf::from(s)));
+        };
+        Ok(Compiler {
+            executable: s.into(),
+            tp,
+        })
+    }
+
+    fn apply_parameters_cc<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(to: &mut cc::Build,
                                                                                                                              parameters: ParameterBundle<Ms,
                                                                                                                                                          Mi,
                                                                                                                                                          Is,
                                                                                                                                                          Ii>)
-                                                                                                                             -> &'t mut cc::Build {
+                                                                                                                             -> Result<&mut cc::Build, ResourceError> {
         for m in parameters.macros {
-            let mut m = m.as_ref().to_str().expect("macros must be UTF-8 in this configuration").splitn(2, '=');
-            to.define(m.next().unwrap(), m.next());
+            let (name, value) = split_macro(m.as_ref())?;
+            to.define(name, value);
         }
         for id in parameters.include_dirs {
             to.include(id.as_ref());
         }
-        to
+        Ok(to)
     }
 
     fn cc_xc(to: &mut cc::Build) -> &mut cc::Build {
@@ -764,14 +1678,6 @@ mod windres {
         to
     }
 
-    fn try_command(cmd: &mut Command, exec: &Path, action: &str, whom: &str, whre: &str) -> Result<(), Cow<'static, str>> {
-        match cmd.status() {
-            Ok(stat) if stat.success() => Ok(()),
-            Ok(stat) => Err(format!("{} failed to {} \"{}\" into \"{}\" with {}", exec.display(), action, whom, whre, stat).into()),
-            Err(e) => Err(format!("Couldn't execute {} to {} \"{}\" into \"{}\": {}", exec.display(), action, whom, whre, e).into()),
-        }
-    }
-
     fn or_curdir(directory: &Path) -> &Path {
         if directory == Path::new("") {
             Path::new(".")
@@ -780,4 +1686,20 @@ mod windres {
         }
     }
 
+
+    #[test]
+    fn versioned_tools() {
+        let root = self::super::test_dir("versioned-tools");
+        for (dir, tool) in [("a", "llvm-rc-17"), ("a", "llvm-rc-9"), ("a", "llvm-rc-x"), ("a", "llvm-rcx-20"), ("b", "llvm-rc-18"), ("b", "llvm-rc-17")] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join(format!("{}{}", tool, env::consts::EXE_SUFFIX)), "").unwrap();
+        }
+
+        let path = env::join_paths([root.join("a"), root.join("b")]).unwrap();
+        let exe = |dir: &str, tool: &str| root.join(dir).join(format!("{}{}", tool, env::consts::EXE_SUFFIX)).into_os_string().into_string().unwrap();
+        assert_eq!(versioned_on_path("llvm-rc", &path),
+                   vec![exe("b", "llvm-rc-18"), exe("a", "llvm-rc-17"), exe("a", "llvm-rc-9")]);
+        assert_eq!(versioned_on_path("llvm-windres", &path), Vec::<String>::new());
+        fs::remove_dir_all(root).unwrap();
+    }
 }
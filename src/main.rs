@@ -1,37 +1,258 @@
-//! Thin wrapper around `embed_resource::compile()`.
+//! Compile resources outside of Cargo, with the same toolchain discovery as `embed_resource::compile()`.
+//!
+//! ```plain
+//! embed-resource compile [-t TARGET] [-c COMPILER] [-D MACRO[=VALUE]]... [-I DIR]... [-o OUTPUT] RESOURCE
+//! embed-resource preprocess [-t TARGET] [-D MACRO[=VALUE]]... [-I DIR]... [-o OUTPUT] RESOURCE
+//! embed-resource find-tool TOOL
+//! embed-resource doctor [-t TARGET] [-c COMPILER] [--json]
+//! ```
+//!
+//! `TARGET` defaults to the host on Windows and `{host arch}-pc-windows-msvc` elsewhere;
+//! `COMPILER` is a path to `windres`, `llvm-rc`, or `RC.EXE`, or `embed-resource` for the built-in one
+//! (by default it's found like in a build script, cf. `$RC`).
+//!
+//! `compile` writes a `.res` file (for MSVC targets) or a COFF object (for everything else) to `OUTPUT`
+//! (`{RESOURCE stem}.res`/`{RESOURCE stem}.o` by default), and a static library containing it next to it
+//! (`{OUTPUT stem}.lib`/`lib{OUTPUT stem}.a`); `preprocess` writes to `OUTPUT` or the standard output.
+//!
+//! `doctor` lists every resource compiler considered for `TARGET` and which one would be used,
+//! then test-compiles a resource with it; `--json` writes just the list, as JSON.
 
 
 extern crate embed_resource;
 
 
+use std::io::{self, Write};
+use std::path::{PathBuf, Path};
+use std::ffi::{OsString, OsStr};
+use std::{env, fs, process};
+use std::hash::{BuildHasher, Hasher};
+use std::collections::hash_map::RandomState;
 use std::borrow::Cow;
-use std::path::Path;
-use std::ffi::OsStr;
-use std::env;
+use std::error::Error;
 
 
 fn main() {
-    env::set_var("TARGET",
-                 if cfg!(target_arch = "x86_64") {
-                     "x86_64"
-                 } else if cfg!(target_arch = "aarch64") {
-                     "aarch64"
-                 } else {
-                     "irrelevant"
-                 });
-    #[cfg(target_os = "windows")]
-    env::set_var("HOST", env::var_os("TARGET").unwrap());
-
-    env::set_var("OUT_DIR", ".");
-
     let mut args = env::args_os();
     let argv0 = args.next().map(Cow::from).unwrap_or(Cow::from(OsStr::new("rust-embed-resource")));
-    let resource = args.next().unwrap_or_else(|| panic!("usage: {} resource [include-dir]", Path::new(&*argv0).display()));
-    let include_dir = args.next();
-    embed_resource::compile(&resource, embed_resource::ParamsMacrosAndIncludeDirs(["VERSION=\"0.5.0\""], include_dir.as_ref())).manifest_required().unwrap();
-    embed_resource::compile_for(&resource,
-                                ["embed_resource", "embed_resource-installer"],
-                                embed_resource::ParamsIncludeDirs(include_dir))
-        .manifest_required()
-        .unwrap();
+    let argv0 = Path::new(&*argv0).display().to_string();
+    let subcommand = args.next();
+    let result = match subcommand.as_ref().and_then(|s| s.to_str()) {
+        Some("compile") => Options::parse(args, true, true).and_then(compile),
+        Some("preprocess") => Options::parse(args, true, false).and_then(preprocess),
+        Some("find-tool") => find_tool(args),
+        Some("doctor") => Options::parse(args, false, true).and_then(doctor),
+        Some("-h" | "--help" | "help") => {
+            print!("{}", usage(&argv0));
+            Ok(())
+        }
+        _ => Err(format!("unknown subcommand {:?}", subcommand.unwrap_or_default())),
+    };
+    if let Err(err) = result {
+        eprintln!("{}: {}", argv0, err);
+        eprint!("{}", usage(&argv0));
+        process::exit(1);
+    }
+}
+
+fn usage(argv0: &str) -> String {
+    format!("usage: {0} compile [-t TARGET] [-c COMPILER] [-D MACRO[=VALUE]]... [-I DIR]... [-o OUTPUT] RESOURCE\n\
+             \x20      {0} preprocess [-t TARGET] [-D MACRO[=VALUE]]... [-I DIR]... [-o OUTPUT] RESOURCE\n\
+             \x20      {0} find-tool TOOL\n\
+             \x20      {0} doctor [-t TARGET] [-c COMPILER] [--json]\n",
+            argv0)
+}
+
+
+#[derive(Debug, Clone, Default)]
+struct Options {
+    target: Option<String>,
+    compiler: Option<OsString>,
+    macros: Vec<OsString>,
+    include_dirs: Vec<OsString>,
+    output: Option<PathBuf>,
+    resource: Option<PathBuf>,
+    json: bool,
+}
+
+impl Options {
+    /// `-c` is only accepted if a `compiler` is used, and `-D`, `-I`, `-o`, and the resource only if a `resource` is
+    fn parse<A: Iterator<Item = OsString>>(mut args: A, resource: bool, compiler: bool) -> Result<Options, String> {
+        let mut ret = Options::default();
+        while let Some(arg) = args.next() {
+            let argument = |args: &mut A| args.next().ok_or_else(|| format!("{} needs an argument", arg.to_string_lossy()));
+            match arg.to_str() {
+                Some("-t" | "--target") => ret.target = Some(argument(&mut args)?.into_string().map_err(|t| format!("invalid target {:?}", t))?),
+                Some("-c" | "--compiler") if compiler => ret.compiler = Some(argument(&mut args)?),
+                Some("-D" | "--define") if resource => ret.macros.push(argument(&mut args)?),
+                Some("-I" | "--include-dir") if resource => ret.include_dirs.push(argument(&mut args)?),
+                Some("-o" | "--output") if resource => ret.output = Some(argument(&mut args)?.into()),
+                Some("--json") if !resource => ret.json = true,
+                Some(a) if a.starts_with('-') && a.len() > 1 => return Err(format!("unknown option {}", a)),
+                _ if resource && ret.resource.is_none() => ret.resource = Some(arg.into()),
+                _ => return Err(format!("unexpected argument {:?}", arg)),
+            }
+        }
+        if resource && ret.resource.is_none() {
+            return Err("no resource file".to_string());
+        }
+        Ok(ret)
+    }
+
+    /// Set up the environment like Cargo would for a build script building for `target`
+    fn apply(&self) -> String {
+        let target = self.target.clone().unwrap_or_else(|| if cfg!(target_os = "windows") {
+            host()
+        } else {
+            format!("{}-pc-windows-msvc", env::consts::ARCH)
+        });
+        env::set_var("TARGET", &target);
+        if env::var_os("HOST").is_none() {
+            env::set_var("HOST", host());
+        }
+        if env::var_os("OPT_LEVEL").is_none() {
+            env::set_var("OPT_LEVEL", "0");
+        }
+        if let Some(compiler) = self.compiler.as_ref() {
+            env::set_var("RC", compiler);
+        }
+        target
+    }
+
+    fn resource(&self) -> embed_resource::Resource {
+        let mut ret = embed_resource::Resource::new(self.resource.as_ref().expect("resource"));
+        for m in &self.macros {
+            let m = m.to_string_lossy();
+            let mut m = m.splitn(2, '=');
+            ret.define(m.next().unwrap(), m.next());
+        }
+        for id in &self.include_dirs {
+            ret.include_dir(id);
+        }
+        ret
+    }
+}
+
+/// The target triple of the machine we're running on, as far as the resource compilers and `cc` are concerned
+fn host() -> String {
+    let (vendor, os) = match env::consts::OS {
+        "windows" if cfg!(target_env = "msvc") => ("pc", "windows-msvc"),
+        "windows" => ("pc", "windows-gnu"),
+        "macos" => ("apple", "darwin"),
+        "linux" if cfg!(target_env = "musl") => ("unknown", "linux-musl"),
+        "linux" => ("unknown", "linux-gnu"),
+        os => ("unknown", os),
+    };
+    format!("{}-{}-{}", env::consts::ARCH, vendor, os)
+}
+
+/// A fresh directory to compile in, removed afterwards
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Result<TempDir, String> {
+        // Under an unpredictable name, and never one that's already there, since anyone can create those in the temporary directory
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        loop {
+            let dir = env::temp_dir().join(format!("rust-embed-resource-{}-{:016x}", process::id(), RandomState::new().build_hasher().finish()));
+            match builder.create(&dir) {
+                Ok(()) => return Ok(TempDir(dir)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(format!("couldn't create {}: {}", dir.display(), e)),
+            }
+        }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+
+fn compile(opts: Options) -> Result<(), String> {
+    let target = opts.apply();
+    let out_dir = TempDir::new()?;
+    let compiled = opts.resource().compile_to(&out_dir.0).map_err(|e| e.to_string())?;
+
+    let output = opts.output.clone().unwrap_or_else(|| {
+        let stem = opts.resource.as_ref().and_then(|r| r.file_stem()).unwrap_or_default();
+        PathBuf::from(stem).with_extension(if target.ends_with("-msvc") { "res" } else { "o" })
+    });
+    let copy = |from: &Path, to: &Path| fs::copy(from, to).map(|_| ()).map_err(|e| format!("couldn't copy {} to {}: {}", from.display(), to.display(), e));
+    copy(&compiled, &output)?;
+
+    // compile_to() leaves the static library next to the compiled resource, with the same stem
+    let (compiled_stem, output_stem) = (compiled.file_stem().unwrap_or_default(), output.file_stem().unwrap_or_default());
+    let archive = |stem: &OsStr| {
+        let mut name = OsString::new();
+        if !target.ends_with("-msvc") {
+            name.push("lib");
+        }
+        name.push(stem);
+        name.push(if target.ends_with("-msvc") { ".lib" } else { ".a" });
+        name
+    };
+    copy(&compiled.with_file_name(archive(compiled_stem)), &output.with_file_name(archive(output_stem)))
+}
+
+fn preprocess(opts: Options) -> Result<(), String> {
+    opts.apply();
+    let preprocessed = opts.resource().preprocess().map_err(|e| match e.source() {
+            Some(source) => format!("{}: {}", e, source),
+            None => e.to_string(),
+        })?;
+    match opts.output.as_ref() {
+        Some(output) => fs::write(output, preprocessed).map_err(|e| format!("couldn't write {}: {}", output.display(), e)),
+        None => io::stdout().write_all(&preprocessed).map_err(|e| e.to_string()),
+    }
+}
+
+fn find_tool<A: Iterator<Item = OsString>>(mut args: A) -> Result<(), String> {
+    let tool = args.next().ok_or("no tool")?.into_string().map_err(|t| format!("invalid tool {:?}", t))?;
+    if let Some(arg) = args.next() {
+        return Err(format!("unexpected argument {:?}", arg));
+    }
+    if env::var_os("HOST").is_none() {
+        env::set_var("HOST", host());
+    }
+    match embed_resource::find_windows_sdk_tool(&tool) {
+        Some(path) => {
+            println!("{}", path.display());
+            Ok(())
+        }
+        None => Err(format!("{} not found", tool)),
+    }
+}
+
+fn doctor(opts: Options) -> Result<(), String> {
+    opts.apply();
+    let report = embed_resource::ToolchainReport::probe();
+    if opts.json {
+        print!("{}", report.to_json());
+        return report.chosen().map(|_| ()).ok_or_else(|| "no resource compiler".to_string());
+    }
+
+    println!("host: {}", env::var("HOST").unwrap_or_default());
+    if let Some(val) = env::var_os("MINGW_CHOST") {
+        println!("$MINGW_CHOST: {}", val.to_string_lossy());
+    }
+    print!("{}", report);
+    if report.chosen().is_none() {
+        return Err("no resource compiler".to_string());
+    }
+
+    let out_dir = TempDir::new()?;
+    let resource = out_dir.0.join("doctor.rc");
+    fs::write(&resource, "1 RCDATA { \"embed-resource\" }\n").map_err(|e| format!("couldn't write {}: {}", resource.display(), e))?;
+    match embed_resource::Resource::new(&resource).compile_to(&out_dir.0) {
+        Ok(_) => {
+            println!("test compilation: OK");
+            Ok(())
+        }
+        Err(err) => Err(format!("test compilation: {}", err)),
+    }
 }
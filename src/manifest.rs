@@ -0,0 +1,273 @@
+use std::collections::BTreeSet;
+use self::super::ResourceError;
+use std::fmt::Write;
+
+
+/// DPI awareness, cf. [`Manifest::dpi_awareness()`].
+///
+/// https://learn.microsoft.com/en-us/windows/win32/hidpi/setting-the-default-dpi-awareness-for-a-process
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DpiAwareness {
+    /// Always scaled by the system
+    Unaware,
+    /// Scaled by the system when moved to a monitor with a different DPI than the primary one
+    System,
+    /// Per-monitor, falling back to [`System`](Self::System) before Windows 8.1
+    PerMonitor,
+    /// Per-monitor v2, falling back to [`PerMonitor`](Self::PerMonitor) before Windows 10 1703 and [`System`](Self::System) before Windows 8.1
+    PerMonitorV2,
+}
+
+/// `requestedExecutionLevel`, cf. [`Manifest::execution_level()`].
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExecutionLevel {
+    /// `asInvoker`: same as the parent process
+    AsInvoker,
+    /// `highestAvailable`: the highest the user can get without a UAC prompt
+    HighestAvailable,
+    /// `requireAdministrator`: always elevated
+    RequireAdministrator,
+}
+
+/// A `supportedOS` entry, cf. [`Manifest::supported_os()`].
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SupportedOs {
+    /// Windows Vista and Server 2008
+    WindowsVista,
+    /// Windows 7 and Server 2008 R2
+    Windows7,
+    /// Windows 8 and Server 2012
+    Windows8,
+    /// Windows 8.1 and Server 2012 R2
+    Windows81,
+    /// Windows 10, Windows 11, and Server 2016 and later
+    Windows10,
+}
+
+impl SupportedOs {
+    fn guid(self) -> &'static str {
+        match self {
+            SupportedOs::WindowsVista => "{e2011457-1546-43c5-a5fe-008deee3d3f0}",
+            SupportedOs::Windows7 => "{35138b9a-5d96-4fbd-8e2d-a2440225f93a}",
+            SupportedOs::Windows8 => "{4a2f28e3-53b9-4441-ba9c-d69d4a4a6e38}",
+            SupportedOs::Windows81 => "{1f676c76-80e1-4239-95bb-83d0f6d0da78}",
+            SupportedOs::Windows10 => "{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}",
+        }
+    }
+}
+
+
+/// An application manifest, cf. [`Resource::manifest()`](crate::Resource::manifest).
+///
+/// Embedded as `RT_MANIFEST` 1 (`CREATEPROCESS_MANIFEST_RESOURCE_ID`),
+/// or 2 (`ISOLATIONAWARE_MANIFEST_RESOURCE_ID`) with [`dll()`](Self::dll).
+///
+/// https://learn.microsoft.com/en-us/windows/win32/sbscs/application-manifests
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+/// use embed_resource::{DpiAwareness, ExecutionLevel, Manifest, Resource, SupportedOs};
+///
+/// fn main() {
+///     Resource::new("checksums.rc")
+///         .manifest(Manifest::new()
+///             .dpi_awareness(DpiAwareness::PerMonitorV2)
+///             .long_path_aware(true)
+///             .execution_level(ExecutionLevel::AsInvoker, false)
+///             .common_controls_v6(true)
+///             .utf8_code_page(true)
+///             .supported_os(SupportedOs::Windows10))
+///         .compile()
+///         .manifest_required()
+///         .unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Manifest {
+    dll: Option<bool>,
+    dpi_awareness: Option<DpiAwareness>,
+    long_path_aware: bool,
+    execution_level: Option<(ExecutionLevel, bool)>,
+    common_controls_v6: bool,
+    utf8_code_page: bool,
+    supported_os: BTreeSet<SupportedOs>,
+}
+
+impl Manifest {
+    /// An empty manifest for an executable.
+    pub fn new() -> Manifest {
+        Manifest::default()
+    }
+
+    /// Whether this is the manifest of a DLL, embedded as ID 2, which can only declare dependencies.
+    ///
+    /// By default it is if the resource is only linked to [`LinkTarget::Cdylib`](crate::LinkTarget::Cdylib).
+    pub fn dll(&mut self, dll: bool) -> &mut Manifest {
+        self.dll = Some(dll);
+        self
+    }
+
+    /// Set `dpiAware` and `dpiAwareness`.
+    pub fn dpi_awareness(&mut self, awareness: DpiAwareness) -> &mut Manifest {
+        self.dpi_awareness = Some(awareness);
+        self
+    }
+
+    /// Set `longPathAware`; Windows 10 only.
+    pub fn long_path_aware(&mut self, aware: bool) -> &mut Manifest {
+        self.long_path_aware = aware;
+        self
+    }
+
+    /// Set `requestedExecutionLevel` and its `uiAccess`.
+    pub fn execution_level(&mut self, level: ExecutionLevel, ui_access: bool) -> &mut Manifest {
+        self.execution_level = Some((level, ui_access));
+        self
+    }
+
+    /// Depend on Common Controls version 6 (visual styles).
+    pub fn common_controls_v6(&mut self, v6: bool) -> &mut Manifest {
+        self.common_controls_v6 = v6;
+        self
+    }
+
+    /// Set `activeCodePage` to UTF-8; Windows 10 1903 only.
+    pub fn utf8_code_page(&mut self, utf8: bool) -> &mut Manifest {
+        self.utf8_code_page = utf8;
+        self
+    }
+
+    /// Add a `supportedOS` entry.
+    pub fn supported_os(&mut self, os: SupportedOs) -> &mut Manifest {
+        self.supported_os.insert(os);
+        self
+    }
+
+    /// The `RT_MANIFEST` ID this manifest is embedded as
+    pub(crate) fn id(&self) -> u16 {
+        if self.dll == Some(true) { 2 } else { 1 }
+    }
+
+    /// This, as the manifest of a DLL unless [`dll()`](Self::dll) says otherwise
+    pub(crate) fn dll_by_default(&self) -> Manifest {
+        Manifest {
+            dll: Some(self.dll.unwrap_or(true)),
+            ..self.clone()
+        }
+    }
+
+    /// Render as XML, failing if any of the options contradict
+    pub fn to_xml(&self) -> Result<String, ResourceError> {
+        if self.dll == Some(true) {
+            let process_wide = [(self.dpi_awareness.is_some(), "DPI awareness"),
+                                (self.long_path_aware, "long path awareness"),
+                                (self.execution_level.is_some(), "execution level"),
+                                (self.utf8_code_page, "active code page"),
+                                (!self.supported_os.is_empty(), "supported OSes")];
+            if let Some((_, what)) = process_wide.iter().find(|(set, _)| *set) {
+                return Err(ResourceError::InvalidResource(format!("DLL manifests can't set {}, which is process-wide", what).into()));
+            }
+        }
+        if !self.supported_os.is_empty() && !self.supported_os.contains(&SupportedOs::Windows10) {
+            let windows10 = [(self.dpi_awareness == Some(DpiAwareness::PerMonitorV2), "per-monitor v2 DPI awareness"),
+                             (self.long_path_aware, "long path awareness"),
+                             (self.utf8_code_page, "the UTF-8 active code page")];
+            if let Some((_, what)) = windows10.iter().find(|(set, _)| *set) {
+                return Err(ResourceError::InvalidResource(format!("{} requires Windows 10, but it's not a supported OS", what).into()));
+            }
+        }
+
+        let mut ret = String::new();
+        let _ = writeln!(ret, r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+        let _ = writeln!(ret, r#"<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">"#);
+        if self.common_controls_v6 {
+            let _ = writeln!(ret, r#"    <dependency>"#);
+            let _ = writeln!(ret, r#"        <dependentAssembly>"#);
+            let _ = writeln!(ret,
+                             r#"            <assemblyIdentity type="win32" name="Microsoft.Windows.Common-Controls" version="6.0.0.0" processorArchitecture="*" publicKeyToken="6595b64144ccf1df" language="*"/>"#);
+            let _ = writeln!(ret, r#"        </dependentAssembly>"#);
+            let _ = writeln!(ret, r#"    </dependency>"#);
+        }
+        if let Some((level, ui_access)) = self.execution_level {
+            let level = match level {
+                ExecutionLevel::AsInvoker => "asInvoker",
+                ExecutionLevel::HighestAvailable => "highestAvailable",
+                ExecutionLevel::RequireAdministrator => "requireAdministrator",
+            };
+            let _ = writeln!(ret, r#"    <trustInfo xmlns="urn:schemas-microsoft-com:asm.v3">"#);
+            let _ = writeln!(ret, r#"        <security>"#);
+            let _ = writeln!(ret, r#"            <requestedPrivileges>"#);
+            let _ = writeln!(ret, r#"                <requestedExecutionLevel level="{}" uiAccess="{}"/>"#, level, ui_access);
+            let _ = writeln!(ret, r#"            </requestedPrivileges>"#);
+            let _ = writeln!(ret, r#"        </security>"#);
+            let _ = writeln!(ret, r#"    </trustInfo>"#);
+        }
+        if !self.supported_os.is_empty() {
+            let _ = writeln!(ret, r#"    <compatibility xmlns="urn:schemas-microsoft-com:compatibility.v1">"#);
+            let _ = writeln!(ret, r#"        <application>"#);
+            for os in &self.supported_os {
+                let _ = writeln!(ret, r#"            <supportedOS Id="{}"/>"#, os.guid());
+            }
+            let _ = writeln!(ret, r#"        </application>"#);
+            let _ = writeln!(ret, r#"    </compatibility>"#);
+        }
+        if self.dpi_awareness.is_some() || self.long_path_aware || self.utf8_code_page {
+            let _ = writeln!(ret, r#"    <application xmlns="urn:schemas-microsoft-com:asm.v3">"#);
+            let _ = writeln!(ret, r#"        <windowsSettings>"#);
+            if let Some(awareness) = self.dpi_awareness {
+                let (aware, awareness) = match awareness {
+                    DpiAwareness::Unaware => ("false", "unaware"),
+                    DpiAwareness::System => ("true", "system"),
+                    DpiAwareness::PerMonitor => ("true/pm", "permonitor"),
+                    DpiAwareness::PerMonitorV2 => ("true/pm", "permonitorv2,permonitor"),
+                };
+                let _ = writeln!(ret, r#"            <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">{}</dpiAware>"#, aware);
+                let _ = writeln!(ret,
+                                 r#"            <dpiAwareness xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">{}</dpiAwareness>"#,
+                                 awareness);
+            }
+            if self.long_path_aware {
+                let _ = writeln!(ret, r#"            <longPathAware xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">true</longPathAware>"#);
+            }
+            if self.utf8_code_page {
+                let _ = writeln!(ret, r#"            <activeCodePage xmlns="http://schemas.microsoft.com/SMI/2019/WindowsSettings">UTF-8</activeCodePage>"#);
+            }
+            let _ = writeln!(ret, r#"        </windowsSettings>"#);
+            let _ = writeln!(ret, r#"    </application>"#);
+        }
+        let _ = writeln!(ret, r#"</assembly>"#);
+        Ok(ret)
+    }
+}
+
+
+#[test]
+fn to_xml() {
+    assert_eq!(Manifest::new().to_xml().unwrap(), include_str!("../test-data/manifest/empty.manifest"));
+    assert_eq!(Manifest::new()
+                   .dpi_awareness(DpiAwareness::PerMonitorV2)
+                   .long_path_aware(true)
+                   .execution_level(ExecutionLevel::RequireAdministrator, false)
+                   .common_controls_v6(true)
+                   .utf8_code_page(true)
+                   .supported_os(SupportedOs::Windows10)
+                   .supported_os(SupportedOs::Windows7)
+                   .supported_os(SupportedOs::Windows10)
+                   .to_xml()
+                   .unwrap(),
+               include_str!("../test-data/manifest/full.manifest"));
+    assert_eq!(Manifest::new().dll(true).common_controls_v6(true).to_xml().unwrap(),
+               include_str!("../test-data/manifest/dll.manifest"));
+}
+
+#[test]
+fn contradictions() {
+    assert!(Manifest::new().dll(true).execution_level(ExecutionLevel::AsInvoker, false).to_xml().is_err());
+    assert!(Manifest::new().dll(true).utf8_code_page(true).to_xml().is_err());
+    assert!(Manifest::new().supported_os(SupportedOs::Windows81).long_path_aware(true).to_xml().is_err());
+    assert!(Manifest::new().supported_os(SupportedOs::Windows7).dpi_awareness(DpiAwareness::PerMonitorV2).to_xml().is_err());
+    assert!(Manifest::new().supported_os(SupportedOs::Windows7).dpi_awareness(DpiAwareness::PerMonitor).to_xml().is_ok());
+    assert!(Manifest::new().utf8_code_page(true).long_path_aware(true).to_xml().is_ok());
+}
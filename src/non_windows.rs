@@ -1,85 +1,174 @@
-use self::super::{ParameterBundle, env_target_and_rc};
+use self::super::{CompilerPreference, ToolchainCandidate, ParameterBundle, ResourceError, CompilerKind};
 use self::super::windres::*;
-use std::ffi::{OsString, OsStr};
+use std::ffi::OsStr;
 use std::path::{PathBuf, Path};
-use std::process::Command;
-use std::borrow::Cow;
-use memchr::memmem;
-use std::mem;
+use std::iter;
 
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub struct ResourceCompiler {
-    compiler: Result<Compiler, Cow<'static, str>>,
+    target: String,
+    /// `Err(None)` if not building for Windows
+    compiler: Result<Compiler, Option<ResourceError>>,
 }
 
 impl ResourceCompiler {
-    pub fn new() -> ResourceCompiler {
-        ResourceCompiler { compiler: Compiler::probe() }
+    pub fn new(target: &str, compilers: &[CompilerPreference]) -> ResourceCompiler {
+        ResourceCompiler::probe(target, compilers, &mut vec![])
+    }
+
+    /// Like `new()`, recording every compiler considered in `candidates`
+    pub fn probe(target: &str, compilers: &[CompilerPreference], candidates: &mut Vec<ToolchainCandidate>) -> ResourceCompiler {
+        ResourceCompiler {
+            target: target.to_string(),
+            compiler: Compiler::probe(target, compilers, candidates),
+        }
     }
 
     #[inline]
-    pub fn is_supported(&mut self) -> Option<Cow<'static, str>> {
-        self.compiler.as_mut().err().map(|e| mem::replace(e, "".into()))
+    pub fn is_supported(&mut self) -> Option<Option<ResourceError>> {
+        self.compiler.as_mut().err().map(Option::take)
+    }
+
+    /// The compiler `compile_resource()` will use
+    pub fn chosen(&self) -> Option<(CompilerKind, &OsStr)> {
+        self.compiler.as_ref().ok().map(|c| (c.tp.kind(), &*c.executable))
+    }
+
+    /// Whether that's the built-in compiler, only because no other was found
+    pub fn is_fallback(&self) -> bool {
+        matches!(self.compiler, Ok(Compiler { tp: CompilerType::Builtin { fallback: true }, .. }))
     }
 
     pub fn compile_resource<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(
         self, out_dir: &str, prefix: &str, resource: &str, parameters: ParameterBundle<Ms, Mi, Is, Ii>)
-        -> Result<String, Cow<'static, str>> {
-        self.compiler.expect("Not supported but we got to compile_resource()?").compile(out_dir,
-                                                                                        prefix,
-                                                                                        format!("{}/{}.lib", out_dir, prefix),
-                                                                                        resource,
-                                                                                        parameters,
-                                                                                        "/fo",
-                                                                                        "/C",
-                                                                                        "/no-preprocess",
-                                                                                        |c| c)
+        -> Result<String, ResourceError> {
+        let compiler = self.compiler.expect("Not supported but we got to compile_resource()?");
+        let target = self.target;
+        // llvm-windres targets its default triple unless it's called {arch}-w64-mingw32-windres
+        let llvm_windres_target = match Path::new(&*compiler.executable).file_name().and_then(OsStr::to_str) {
+            Some(exe) if compiler.tp == CompilerType::WindRes && exe.starts_with("llvm-windres") => {
+                Some(format!("{}-w64-mingw32", &target[0..target.find('-').unwrap_or_default()]))
+            }
+            _ => None,
+        };
+        compiler.compile(&target,
+                         out_dir,
+                         prefix,
+                         format!("{}/{}.{}", out_dir, prefix, if target.ends_with("-msvc") { "res" } else { "o" }),
+                         resource,
+                         parameters,
+                         "/fo",
+                         "/C",
+                         "/no-preprocess",
+                         |c| match llvm_windres_target {
+                             Some(target) => c.arg("--target").arg(target),
+                             None => c,
+                         })
     }
 }
 
 
 impl Compiler {
-    fn probe() -> Result<Compiler, Cow<'static, str>> {
-        let (target, rc) = env_target_and_rc()?;
-        if let Some(rc) = rc {
-            return guess_compiler_variant(rc);
+    fn probe(target: &str, compilers: &[CompilerPreference], candidates: &mut Vec<ToolchainCandidate>) -> Result<Compiler, Option<ResourceError>> {
+        if let Some((source, compilers)) = CompilerPreference::resolve(compilers, target)? {
+            return Compiler::prefer(&compilers, &source, candidates).map_err(Some);
+        }
+        if let Some(compiler) = Compiler::from_env(target, candidates) {
+            return compiler.map_err(Some);
         }
 
-        if target.ends_with("-windows-gnu") || target.ends_with("-windows-gnullvm") {
+        let compiler = if target.ends_with("-windows-gnu") || target.ends_with("-windows-gnullvm") {
             let executable = format!("{}-w64-mingw32-windres", &target[0..target.find('-').unwrap_or_default()]);
-            Compiler::windres(executable.into())
+            Compiler::discover(candidates, CompilerKind::WindRes, iter::once(default_tool(executable)).chain(llvm_tool("llvm-windres")))
         } else if target.ends_with("-windows-msvc") {
-            Compiler::llvm_rc("llvm-rc".into())
+            Compiler::discover(candidates, CompilerKind::LlvmRc, llvm_tool("llvm-rc"))
         } else {
-            Err("".into())
-        }
+            return Err(None);
+        };
+        compiler.or_else(|_| Compiler::consider(candidates, "fallback", OsStr::new("embed-resource"), Some(CompilerKind::Builtin), Ok(Compiler::builtin(true))))
+            .map_err(Some)
     }
 }
 
-/// -V will print the version in windres.
-/// /? will print the help in LLVM-RC and Microsoft RC.EXE.
-/// If combined, /? takes precedence over -V.
-fn guess_compiler_variant(s: OsString) -> Result<Compiler, Cow<'static, str>> {
-    match Command::new(&s).args(&["-V", "/?"]).output() {
-        Ok(out) => {
-            let tp = if out.stdout.starts_with(b"GNU windres") {
-                CompilerType::WindRes
-            } else if out.stdout.starts_with(b"OVERVIEW: Resource Converter") || out.stdout.starts_with(b"OVERVIEW: LLVM Resource Converter") {
-                CompilerType::LlvmRc { has_no_preprocess: memmem::find(&out.stdout, b"no-preprocess").is_some() }
-            } else {
-                return Err(format!("Unknown RC compiler variant: {}", Path::new(&s).display()).into()); // TODO (MSRV 1.87): s.display()
-            };
-            Ok(Compiler {
-                executable: s.into(),
-                tp: tp,
-            })
-        }
-        Err(err) => Err(format!("Couldn't execute {}: {}", Path::new(&s).display(), err).into()),
-    }
-}
 
 
 pub fn find_windows_sdk_tool_impl(_: &str) -> Option<PathBuf> {
     None
 }
+
+
+#[cfg(unix)]
+#[test]
+fn zig_rc() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::fs;
+
+    // Answers rc /? like zig, otherwise records its arguments and "compiles" to t.llvm-rc.res
+    let dir = self::super::test_dir("zig-rc");
+    let zig = dir.join("zig");
+    fs::write(&zig,
+              format!("#!/bin/sh\n\
+                       [ \"$1 $2\" = 'rc /?' ] && exec echo 'Usage: zig rc [options] [--] <INPUT> [<OUTPUT>]'\n\
+                       printf '%s\\n' \"$@\" > \"$0.args\"\n\
+                       while [ $# -gt 0 ]; do [ \"$1\" = /fo ] && cp '{}' \"$2\"; shift; done\n",
+                      Path::new("test-data/builtin/t.llvm-rc.res").canonicalize().unwrap().display()))
+        .unwrap();
+    fs::set_permissions(&zig, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let compiler = guess_compiler_variant(zig.clone().into()).unwrap();
+    assert_eq!(compiler.tp, CompilerType::ZigRc);
+
+    let dir = dir.to_str().unwrap();
+    let out_file = compiler.compile("x86_64-pc-windows-gnu",
+                 dir,
+                 "t",
+                 format!("{}/t.lib", dir),
+                 "test-data/builtin/t.rc",
+                 ParameterBundle {
+                     macros: &["UNUSED=1"],
+                     include_dirs: &["test-data"],
+                 },
+                 "/fo",
+                 "/C",
+                 "/no-preprocess",
+                 |c| c)
+        .unwrap();
+    assert_eq!(fs::read_to_string(format!("{}/zig.args", dir)).unwrap(),
+               format!("rc\n/fo\n{0}/t.lib\n/c65001\n/i\n{0}\n/d\nUNUSED=1\n/i\ntest-data\n--\ntest-data/builtin/t.rc\n", dir));
+    // Converted to an object for GNU
+    assert_eq!(fs::read(out_file).unwrap(), &include_bytes!("../test-data/coff/t-X64.obj")[..]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fallback_unsupported() {
+    use self::super::{CompilationResult, compile_failed};
+    use std::fs;
+
+    let dir = self::super::test_dir("fallback-unsupported");
+    let rc = dir.join("menu.rc");
+    fs::write(&rc, "1 MENU { }\n").unwrap();
+
+    for fallback in [false, true] {
+        let comp = ResourceCompiler {
+            target: "x86_64-pc-windows-gnu".to_string(),
+            compiler: Ok(Compiler::builtin(fallback)),
+        };
+        assert_eq!(comp.is_fallback(), fallback);
+        let err = comp.compile_resource(dir.to_str().unwrap(),
+                              "menu",
+                              rc.to_str().unwrap(),
+                              ParameterBundle {
+                                  macros: &[] as &[&str],
+                                  include_dirs: &[] as &[&str],
+                              })
+            .unwrap_err();
+        match compile_failed(err, fallback) {
+            CompilationResult::NotAttempted(ResourceError::InvalidResource(_)) => assert!(fallback),
+            CompilationResult::Failed(ResourceError::InvalidResource(_)) => assert!(!fallback),
+            res => panic!("{:?}", res),
+        }
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,195 @@
+//! The `.res` format, as produced by `RC.EXE`, `llvm-rc`, and `windres -O res`
+//!
+//! https://learn.microsoft.com/en-us/windows/win32/menurc/resourceheader
+
+
+use std::fmt::{self, Display};
+use std::borrow::Cow;
+
+
+/// `MOVEABLE`
+pub const MEMORY_MOVEABLE: u16 = 0x0010;
+/// `PURE`
+pub const MEMORY_PURE: u16 = 0x0020;
+/// `PRELOAD`
+pub const MEMORY_PRELOAD: u16 = 0x0040;
+/// `DISCARDABLE`
+pub const MEMORY_DISCARDABLE: u16 = 0x1000;
+
+
+/// A resource type or name: either a 16-bit ordinal or an (uppercase) string
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResId {
+    Ordinal(u16),
+    Name(String),
+}
+
+impl Display for ResId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ResId::Ordinal(o) => write!(f, "{}", o),
+            ResId::Name(n) => f.write_str(n),
+        }
+    }
+}
+
+
+/// A single resource in a `.res` file
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResEntry {
+    pub tp: ResId,
+    pub name: ResId,
+    pub language: u16,
+    pub memory_flags: u16,
+    pub data_version: u32,
+    pub version: u32,
+    pub characteristics: u32,
+    pub data: Vec<u8>,
+}
+
+impl ResEntry {
+    pub fn new(tp: ResId, name: ResId, language: u16, memory_flags: u16, data: Vec<u8>) -> ResEntry {
+        ResEntry {
+            tp,
+            name,
+            language,
+            memory_flags,
+            data_version: 0,
+            version: 0,
+            characteristics: 0,
+            data,
+        }
+    }
+}
+
+
+/// Serialise `entries` into a `.res` file, including the leading empty entry
+pub fn write(entries: &[ResEntry]) -> Vec<u8> {
+    let mut out = vec![];
+    // The null entry that identifies 32-bit resource files
+    write_entry(&mut out, &ResEntry::new(ResId::Ordinal(0), ResId::Ordinal(0), 0, 0, vec![]));
+    for e in entries {
+        write_entry(&mut out, e);
+    }
+    out
+}
+
+fn write_entry(out: &mut Vec<u8>, entry: &ResEntry) {
+    let mut header = vec![];
+    write_id(&mut header, &entry.tp);
+    write_id(&mut header, &entry.name);
+    pad4(&mut header);
+    header.extend_from_slice(&entry.data_version.to_le_bytes());
+    header.extend_from_slice(&entry.memory_flags.to_le_bytes());
+    header.extend_from_slice(&entry.language.to_le_bytes());
+    header.extend_from_slice(&entry.version.to_le_bytes());
+    header.extend_from_slice(&entry.characteristics.to_le_bytes());
+
+    out.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(header.len() as u32 + 8).to_le_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&entry.data);
+    pad4(out);
+}
+
+fn write_id(out: &mut Vec<u8>, id: &ResId) {
+    match id {
+        ResId::Ordinal(o) => {
+            out.extend_from_slice(&0xFFFFu16.to_le_bytes());
+            out.extend_from_slice(&o.to_le_bytes());
+        }
+        ResId::Name(n) => {
+            for c in n.encode_utf16() {
+                out.extend_from_slice(&c.to_le_bytes());
+            }
+            out.extend_from_slice(&[0, 0]);
+        }
+    }
+}
+
+
+/// Parse a `.res` file, skipping the leading empty entry
+pub fn read(data: &[u8]) -> Result<Vec<ResEntry>, Cow<'static, str>> {
+    let mut entries = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        let data_size = read_u32(data, offset)? as usize;
+        let header_size = read_u32(data, offset + 4)? as usize;
+        let mut cur = offset + 8;
+        let tp = read_id(data, &mut cur)?;
+        let name = read_id(data, &mut cur)?;
+        cur = offset + align4(cur - offset);
+        let data_version = read_u32(data, cur)?;
+        let memory_flags = read_u16(data, cur + 4)?;
+        let language = read_u16(data, cur + 6)?;
+        let version = read_u32(data, cur + 8)?;
+        let characteristics = read_u32(data, cur + 12)?;
+
+        let start = offset + header_size;
+        let entry_data = data.get(start..start + data_size).ok_or_else(|| format!("Truncated resource {} {} at {:#x}", tp, name, offset))?;
+        if offset == 0 {
+            if data_size != 0 || tp != ResId::Ordinal(0) || name != ResId::Ordinal(0) {
+                return Err("Not a 32-bit resource file: no leading empty entry".into());
+            }
+        } else {
+            entries.push(ResEntry {
+                tp,
+                name,
+                language,
+                memory_flags,
+                data_version,
+                version,
+                characteristics,
+                data: entry_data.to_vec(),
+            });
+        }
+        offset = align4(start + data_size);
+    }
+    Ok(entries)
+}
+
+fn read_id(data: &[u8], cur: &mut usize) -> Result<ResId, Cow<'static, str>> {
+    let first = read_u16(data, *cur)?;
+    if first == 0xFFFF {
+        let ordinal = read_u16(data, *cur + 2)?;
+        *cur += 4;
+        return Ok(ResId::Ordinal(ordinal));
+    }
+
+    let mut units = vec![];
+    loop {
+        let c = read_u16(data, *cur)?;
+        *cur += 2;
+        if c == 0 {
+            break;
+        }
+        units.push(c);
+    }
+    String::from_utf16(&units).map(ResId::Name).map_err(|_| format!("Resource name at {:#x} not valid UTF-16", *cur).into())
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, Cow<'static, str>> {
+    data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or_else(|| format!("Truncated resource file at {:#x}", at).into())
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, Cow<'static, str>> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(|| format!("Truncated resource file at {:#x}", at).into())
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Pad with zeroes to a `DWORD` boundary
+pub fn pad4(out: &mut Vec<u8>) {
+    while out.len() % 4 != 0 {
+        out.push(0);
+    }
+}
+
+
+#[test]
+fn read_roundtrip() {
+    let res = include_bytes!("../test-data/builtin/t.llvm-rc.res");
+    assert_eq!(&write(&read(res).unwrap())[..], &res[..]);
+}
@@ -0,0 +1,384 @@
+use self::super::{CompilerPreference, CompilationResult, ParameterBundle, ResourceError, VersionInfo, Manifest, OUTSIDE_CARGO, compile_and_link, compile_impl,
+                  env_target_and_rc, env_target, write_archive, preprocess, icon};
+use std::ffi::{OsString, OsStr};
+use std::path::{PathBuf, Path};
+use std::borrow::Cow;
+use std::env;
+
+
+/// Where to link a compiled resource to, cf. [`Resource::link_to()`].
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinkTarget {
+    /// All binaries, or the library if there are none; this is what [`compile()`](crate::compile) does
+    AllBins,
+    /// Only the specified binaries, like [`compile_for()`](crate::compile_for)
+    Bins(Vec<String>),
+    /// Test binaries, like [`compile_for_tests()`](crate::compile_for_tests)
+    Tests,
+    /// Benchmarks, like [`compile_for_benchmarks()`](crate::compile_for_benchmarks)
+    Benchmarks,
+    /// Examples, like [`compile_for_examples()`](crate::compile_for_examples)
+    Examples,
+    /// Every artifact, like [`compile_for_everything()`](crate::compile_for_everything)
+    Everything,
+    /// The `cdylib`, like [`compile_for_cdylib()`](crate::compile_for_cdylib).
+    ///
+    /// If this is the only target, the [`manifest()`](Resource::manifest) is a DLL's and the
+    /// [`version_info()`](Resource::version_info) is `VFT_DLL`, unless set otherwise
+    Cdylib,
+    /// Crates depending on this one, whose build scripts call [`link_dependency_resources()`](crate::link_dependency_resources)
+    /// with this package's `links` key, which it must have
+    Dependents,
+}
+
+impl LinkTarget {
+    /// Whether anything linked to both would end up in the same artifact
+    pub(crate) fn overlaps(&self, other: &LinkTarget) -> bool {
+        match (self, other) {
+            (LinkTarget::Dependents, _) | (_, LinkTarget::Dependents) => false,
+            (LinkTarget::Everything, _) | (_, LinkTarget::Everything) => true,
+            (LinkTarget::AllBins, LinkTarget::AllBins | LinkTarget::Bins(_)) | (LinkTarget::Bins(_), LinkTarget::AllBins) => true,
+            (LinkTarget::Bins(lhs), LinkTarget::Bins(rhs)) => lhs.iter().any(|bin| rhs.contains(bin)),
+            (lhs, rhs) => lhs == rhs,
+        }
+    }
+}
+
+
+/// A resource file, and everything needed to compile and link it.
+///
+/// This is equivalent to the [`compile()`](crate::compile) and `compile_for*()` functions,
+/// but keeps all parameters in one place instead of in type parameters.
+///
+/// Generated resources, like [`version_info()`](Self::version_info), are written to a resource file in `$OUT_DIR`
+/// that `#include`s the original one (if any), and that's compiled instead.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+/// use embed_resource::{LinkTarget, Resource};
+///
+/// fn main() {
+///     Resource::new("assets/poke-a-mango.rc")
+///         .define("VERSION", Some("\"0.5.0\""))
+///         .include_dir("assets/include")
+///         .link_to(LinkTarget::Bins(vec!["poke-a-mango".to_string(), "poke-a-mango-installer".to_string()]))
+///         .compile()
+///         .manifest_required()
+///         .unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Resource {
+    resource_file: Option<PathBuf>,
+    macros: Vec<OsString>,
+    include_dirs: Vec<OsString>,
+    link_targets: Vec<LinkTarget>,
+    version_info: Option<VersionInfo>,
+    manifest: Option<Manifest>,
+    icons: Vec<(u16, Vec<PathBuf>)>,
+    compilers: Vec<CompilerPreference>,
+}
+
+impl Resource {
+    /// Prepare to compile the specified resource file, relative to the crate root.
+    ///
+    /// Without any [`link_to()`](Self::link_to) calls the resource is linked to [`LinkTarget::AllBins`].
+    pub fn new<T: AsRef<Path>>(resource_file: T) -> Resource {
+        Resource {
+            resource_file: Some(resource_file.as_ref().to_path_buf()),
+            ..Resource::generated()
+        }
+    }
+
+    /// Prepare to compile only generated resources, without a resource file.
+    pub fn generated() -> Resource {
+        Resource {
+            resource_file: None,
+            macros: vec![],
+            include_dirs: vec![],
+            link_targets: vec![],
+            version_info: None,
+            manifest: None,
+            icons: vec![],
+            compilers: vec![],
+        }
+    }
+
+    /// Define a macro (`-D`/`/D`), with an optional value.
+    pub fn define<'v, V: Into<Option<&'v str>>>(&mut self, name: &str, value: V) -> &mut Resource {
+        self.macros.push(match value.into() {
+            Some(value) => format!("{}={}", name, value).into(),
+            None => name.into(),
+        });
+        self
+    }
+
+    /// Add an include directory (`-I`/`/I`).
+    pub fn include_dir<D: AsRef<OsStr>>(&mut self, dir: D) -> &mut Resource {
+        self.include_dirs.push(dir.as_ref().to_os_string());
+        self
+    }
+
+    /// Link the compiled resource to this target, in addition to any previously specified.
+    ///
+    /// The resource is still only compiled once.
+    pub fn link_to(&mut self, target: LinkTarget) -> &mut Resource {
+        self.link_targets.push(target);
+        self
+    }
+
+    /// Add a `VERSIONINFO` resource, replacing the previous one, if any.
+    ///
+    /// The resource file mustn't have one already.
+    pub fn version_info(&mut self, info: &VersionInfo) -> &mut Resource {
+        self.version_info = Some(info.clone());
+        self
+    }
+
+    /// Add an application manifest, replacing the previous one, if any.
+    ///
+    /// The resource file mustn't have one with the same ID already.
+    ///
+    /// If the resource is only linked to [`LinkTarget::Cdylib`], it's a DLL manifest unless [`Manifest::dll()`] says otherwise,
+    /// and compiling fails if it sets any process-wide options, like [`Manifest::dpi_awareness()`].
+    pub fn manifest(&mut self, manifest: &Manifest) -> &mut Resource {
+        self.manifest = Some(manifest.clone());
+        self
+    }
+
+    /// Add an icon assembled from PNGs, relative to the crate root, replacing the previous one with the same ID, if any.
+    ///
+    /// The images must be square and at most 256x256, and should include at least 16x16, 32x32, 48x48, and 256x256.
+    /// 256x256 images are embedded as PNGs, smaller ones are converted to bitmaps.
+    ///
+    /// Explorer uses the icon with the lowest ID as the application icon.
+    pub fn icon<P: AsRef<Path>>(&mut self, id: u16, pngs: &[P]) -> &mut Resource {
+        let pngs = pngs.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        match self.icons.iter_mut().find(|(i, _)| *i == id) {
+            Some((_, p)) => *p = pngs,
+            None => self.icons.push((id, pngs)),
+        }
+        self
+    }
+
+    /// Try these resource compilers, in order, instead of the usual choice (and `$EMBED_RESOURCE_COMPILERS`, and `$RC`);
+    /// the first one that runs is used.
+    ///
+    /// If none do, the compilation isn't attempted, and the error says why each one didn't.
+    ///
+    /// ```rust,no_run
+    /// # use embed_resource::{CompilerPreference, Resource};
+    /// Resource::new("app.rc")
+    ///     .compilers(&[CompilerPreference::LlvmRc("llvm-rc-18".into()),
+    ///                  CompilerPreference::WindRes("x86_64-w64-mingw32-windres".into()),
+    ///                  CompilerPreference::Builtin])
+    ///     .compile()
+    ///     .manifest_required()
+    ///     .unwrap();
+    /// ```
+    pub fn compilers(&mut self, compilers: &[CompilerPreference]) -> &mut Resource {
+        self.compilers = compilers.to_vec();
+        self
+    }
+
+    /// Compile the resource and link it to all specified targets.
+    ///
+    /// See [`compile()`](crate::compile) for details.
+    pub fn compile(&self) -> CompilationResult {
+        let link_targets = if self.link_targets.is_empty() {
+            &[LinkTarget::AllBins]
+        } else {
+            &self.link_targets[..]
+        };
+
+        match self.prepare() {
+            Ok(Some((resource_file, generated, include_dirs))) => {
+                compile_and_link(&resource_file,
+                                 generated.as_ref(),
+                                 ParameterBundle {
+                                     macros: &self.macros,
+                                     include_dirs: &include_dirs,
+                                 },
+                                 link_targets,
+                                 &self.compilers)
+            }
+            Ok(None) => CompilationResult::Ok,
+            Err(err) => CompilationResult::Failed(err),
+        }
+    }
+
+    /// Compile the resource for `$TARGET` into `out_dir`, outside of a build script: nothing is linked and no `cargo:`
+    /// directives are printed.
+    ///
+    /// Returns the path to the compiled resource: a `.res` for MSVC targets, a COFF object (`.o`) otherwise.
+    /// Its name is the resource's stem followed by a hash of its path and parameters,
+    /// so resources with the same name don't overwrite each other.
+    ///
+    /// Next to it is a static library with the same name (`{name}.lib` for MSVC targets, `lib{name}.a` otherwise) that contains it.
+    pub fn compile_to<P: AsRef<Path>>(&self, out_dir: P) -> Result<PathBuf, CompilationResult> {
+        let target = env_target()?;
+        self.compile_to_for(&target, out_dir.as_ref())
+    }
+
+    /// [`compile_to()`](Self::compile_to), for `target` instead of `$TARGET`
+    pub(crate) fn compile_to_for(&self, target: &str, out_dir: &Path) -> Result<PathBuf, CompilationResult> {
+        let (resource_file, generated, include_dirs) = self.prepare()
+            .map_err(CompilationResult::Failed)?
+            .ok_or_else(|| CompilationResult::Failed(ResourceError::InvalidResource("Nothing to compile".into())))?;
+
+        OUTSIDE_CARGO.with(|oc| oc.set(true));
+        let ret = compile_impl(target,
+                               &resource_file,
+                               generated.as_ref(),
+                               ParameterBundle {
+                                   macros: &self.macros,
+                                   include_dirs: &include_dirs,
+                               },
+                               Some(out_dir),
+                               &self.compilers);
+        OUTSIDE_CARGO.with(|oc| oc.set(false));
+        let (prefix, out_dir, out_file) = ret?;
+        write_archive(target, &out_dir, &prefix, &out_file).map_err(CompilationResult::Failed)?;
+        Ok(PathBuf::from(out_file))
+    }
+
+    /// Run the resource file through the C preprocessor for `$TARGET`, like before compiling with LLVM-RC, and return the result.
+    ///
+    /// Generated resources aren't included. Like [`compile_to()`](Self::compile_to), this prints no `cargo:` directives.
+    pub fn preprocess(&self) -> Result<Vec<u8>, ResourceError> {
+        let resource_file = self.resource_file.as_ref().ok_or_else(|| ResourceError::InvalidResource("No resource file to preprocess".into()))?;
+        let (target, _) = env_target_and_rc()?;
+
+        OUTSIDE_CARGO.with(|oc| oc.set(true));
+        let ret = preprocess(&target, resource_file, &self.macros, &self.include_dirs, None);
+        OUTSIDE_CARGO.with(|oc| oc.set(false));
+        ret
+    }
+
+    /// The resource file to compile, what to generate for it, and the include directories to use, or `None` if there's nothing to
+    /// compile
+    fn prepare(&self) -> Result<Option<Prepared>, ResourceError> {
+        let stem = match self.resource_file.as_ref() {
+            Some(resource_file) => resource_file.file_stem().and_then(OsStr::to_str).ok_or_else(|| ResourceError::InvalidPath(resource_file.clone()))?,
+            None => "embed-resource",
+        };
+        let generated = self.generate(stem)?;
+        Ok(match (&self.resource_file, generated) {
+            (Some(resource_file), None) => Some((resource_file.clone(), None, self.include_dirs.clone())),
+            (resource_file, Some(mut generated)) => {
+                // The original's relative paths must still resolve from $OUT_DIR
+                let mut include_dirs = self.include_dirs.clone();
+                if let Some(resource_file) = resource_file {
+                    let resource_file = env::current_dir().map(|cd| cd.join(resource_file)).unwrap_or_else(|_| resource_file.clone());
+                    if let Some(parent) = resource_file.parent() {
+                        include_dirs.push(parent.as_os_str().to_os_string());
+                    }
+                    generated.rc.insert_str(0, &format!("#include \"{}\"\n", resource_file.display()));
+                }
+
+                Some((PathBuf::from(format!("{}-generated.rc", stem)), Some(generated), include_dirs))
+            }
+            (None, None) => None,
+        })
+    }
+
+    /// The resource script for all generated resources and the files it needs, if any
+    fn generate(&self, stem: &str) -> Result<Option<Generated>, ResourceError> {
+        let mut ret = Generated {
+            rc: String::new(),
+            files: vec![],
+            deps: vec![],
+        };
+        let dll = !self.link_targets.is_empty() && self.link_targets.iter().all(|t| *t == LinkTarget::Cdylib);
+        if let Some(vi) = self.version_info.as_ref() {
+            ret.rc.push_str(&if dll { vi.dll_by_default().to_rc() } else { vi.to_rc() });
+            // For VersionInfo::from_cargo_env()
+            ret.deps.push(Path::new(&env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default()).join("Cargo.toml"));
+        }
+        if let Some(manifest) = self.manifest.as_ref() {
+            let defaulted = dll && manifest.dll_by_default() != *manifest;
+            let manifest = if dll { Cow::Owned(manifest.dll_by_default()) } else { Cow::Borrowed(manifest) };
+            let xml = manifest.to_xml().map_err(|err| match err {
+                    ResourceError::InvalidResource(err) if defaulted => {
+                        ResourceError::InvalidResource(format!("{} (it's a DLL manifest because the resource is only linked to the cdylib; \
+                                                                set Manifest::dll(false) to embed an application manifest)",
+                                                               err)
+                            .into())
+                    }
+                    err => err,
+                })?;
+            let name = format!("{}-generated.manifest", stem);
+            ret.rc.push_str(&format!("{} 24 \"{}\"\n", manifest.id(), name));
+            ret.files.push((name, xml.into_bytes()));
+        }
+        for (id, pngs) in &self.icons {
+            let name = format!("{}-generated-{}.ico", stem, id);
+            ret.rc.push_str(&format!("{} ICON \"{}\"\n", id, name));
+            ret.files.push((name, icon::ico_from_pngs(pngs).map_err(ResourceError::InvalidResource)?));
+            ret.deps.extend(pngs.iter().cloned());
+        }
+        Ok(if ret.rc.is_empty() { None } else { Some(ret) })
+    }
+}
+
+
+/// The resource file to compile, what to generate for it, and the include directories to use, cf. [`Resource::prepare()`]
+type Prepared = (PathBuf, Option<Generated>, Vec<OsString>);
+
+/// A resource script, and the files it refers to by name, to write to `$OUT_DIR` and compile
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub(crate) struct Generated {
+    pub rc: String,
+    pub files: Vec<(String, Vec<u8>)>,
+    /// Files the generated ones were made from
+    pub deps: Vec<PathBuf>,
+}
+
+
+#[test]
+fn cdylib_defaults() {
+    let rc = |link_targets: &[LinkTarget], vi: &VersionInfo, manifest: &Manifest| {
+        let mut res = Resource::generated();
+        res.version_info(vi).manifest(manifest);
+        for t in link_targets {
+            res.link_to(t.clone());
+        }
+        res.generate("t").unwrap().unwrap().rc
+    };
+
+    let cdylib = rc(&[LinkTarget::Cdylib], &VersionInfo::new(), &Manifest::new());
+    assert!(cdylib.contains("FILETYPE 0x2\n"));
+    assert!(cdylib.contains("2 24 \"t-generated.manifest\"\n"));
+
+    for link_targets in [&[][..], &[LinkTarget::AllBins, LinkTarget::Cdylib]] {
+        let app = rc(link_targets, &VersionInfo::new(), &Manifest::new());
+        assert!(app.contains("FILETYPE 0x1\n"));
+        assert!(app.contains("1 24 \"t-generated.manifest\"\n"));
+    }
+
+    let explicit = rc(&[LinkTarget::Cdylib], VersionInfo::new().file_type(1), Manifest::new().dll(false));
+    assert!(explicit.contains("FILETYPE 0x1\n"));
+    assert!(explicit.contains("1 24 \"t-generated.manifest\"\n"));
+
+    let dpi_aware = Resource::generated().manifest(Manifest::new().dpi_awareness(self::super::DpiAwareness::PerMonitorV2)).link_to(LinkTarget::Cdylib).generate("t");
+    match dpi_aware {
+        Err(ResourceError::InvalidResource(err)) => assert!(err.contains("DPI awareness") && err.contains("Manifest::dll(false)"), "{}", err),
+        _ => panic!("{:?}", dpi_aware),
+    }
+}
+
+#[test]
+fn overlaps() {
+    let bins = |names: &[&str]| LinkTarget::Bins(names.iter().map(|n| n.to_string()).collect());
+    assert!(LinkTarget::AllBins.overlaps(&bins(&["a"])));
+    assert!(bins(&["a", "b"]).overlaps(&bins(&["b"])));
+    assert!(!bins(&["a"]).overlaps(&bins(&["b"])));
+    assert!(LinkTarget::Everything.overlaps(&LinkTarget::Tests));
+    assert!(LinkTarget::Tests.overlaps(&LinkTarget::Tests));
+    assert!(!LinkTarget::Tests.overlaps(&LinkTarget::AllBins));
+    assert!(!LinkTarget::Examples.overlaps(&LinkTarget::Cdylib));
+    assert!(!LinkTarget::Dependents.overlaps(&LinkTarget::Dependents));
+    assert!(!LinkTarget::Everything.overlaps(&LinkTarget::Dependents));
+}
@@ -0,0 +1,95 @@
+//! The package's binary targets, found like Cargo does
+//!
+//! `[[bin]]`s are taken as declared; unless `package.autobins = false` (or the edition is 2015 and there are some),
+//! `src/main.rs` (named after the package), `src/bin/*.rs`, and `src/bin/*/main.rs` are added,
+//! if no declared one has the same name or path.
+//!
+//! https://doc.rust-lang.org/cargo/reference/cargo-targets.html#target-auto-discovery
+
+
+use self::super::ResourceError;
+use std::path::{PathBuf, Path};
+use toml::Table as TomlTable;
+use std::{env, fs};
+
+
+/// The names of the binaries in the package being built, from `$CARGO_MANIFEST_DIR/Cargo.toml`
+pub fn binaries() -> Result<Vec<String>, ResourceError> {
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").ok_or(ResourceError::MissingEnv("CARGO_MANIFEST_DIR"))?);
+    let cargo_toml = manifest_dir.join("Cargo.toml");
+    let manifest = fs::read_to_string(&cargo_toml)
+        .map_err(|e| ResourceError::io(&cargo_toml, e))?
+        .parse::<TomlTable>()
+        .map_err(|e| {
+            ResourceError::InvalidCargoToml {
+                path: cargo_toml.clone(),
+                message: e.message().to_string(),
+            }
+        })?;
+    Ok(binaries_in(&manifest_dir, &manifest))
+}
+
+fn binaries_in(manifest_dir: &Path, manifest: &TomlTable) -> Vec<String> {
+    let package = manifest.get("package").and_then(|p| p.as_table());
+    let declared = manifest.get("bin")
+        .and_then(|b| b.as_array())
+        .map(|bins| {
+            bins.iter()
+                .filter_map(|b| b.as_table())
+                .filter_map(|b| {
+                    let path = b.get("path").and_then(|p| p.as_str()).map(|p| manifest_dir.join(p));
+                    b.get("name").and_then(|n| n.as_str()).map(|n| (n.to_string(), path))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    // An inherited edition is 2015 only if the workspace's is, but that's been unusual for a while
+    let edition_2015 = package.map(|p| p.get("edition").map(|e| e.as_str() == Some("2015")).unwrap_or(true)).unwrap_or(false);
+    let autobins = package.and_then(|p| p.get("autobins")).and_then(|a| a.as_bool()).unwrap_or(!edition_2015 || declared.is_empty());
+
+    let mut ret = declared.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    if autobins {
+        let mut inferred = vec![];
+        if let Some(name) = package.and_then(|p| p.get("name")).and_then(|n| n.as_str()) {
+            inferred.push((name.to_string(), manifest_dir.join("src").join("main.rs")));
+        }
+        if let Ok(bin_dir) = fs::read_dir(manifest_dir.join("src").join("bin")) {
+            let mut found = bin_dir.flatten()
+                .map(|e| e.path())
+                .filter_map(|path| if path.is_dir() {
+                    Some((path.file_name()?.to_str()?.to_string(), path.join("main.rs")))
+                } else if path.extension().map(|e| e == "rs").unwrap_or(false) {
+                    Some((path.file_stem()?.to_str()?.to_string(), path))
+                } else {
+                    None
+                })
+                .collect::<Vec<_>>();
+            found.sort();
+            inferred.extend(found);
+        }
+
+        for (name, path) in inferred {
+            if path.is_file() && !declared.iter().any(|(n, p)| *n == name || p.as_ref() == Some(&path)) && !ret.contains(&name) {
+                ret.push(name);
+            }
+        }
+    }
+    ret
+}
+
+
+#[test]
+fn discovery() {
+    let dir = Path::new("test-data/targets");
+    let bins = |manifest: &str| binaries_in(dir, &manifest.parse().unwrap());
+
+    assert_eq!(bins("[package]\nname = \"pkg\"\nedition = \"2021\""), ["pkg", "extra", "multi"]);
+    assert_eq!(bins("[package]\nname = \"pkg\"\nedition = \"2021\"\nautobins = false"), Vec::<String>::new());
+    assert_eq!(bins("[package]\nname = \"pkg\"\nedition = \"2021\"\nautobins = false\n[[bin]]\nname = \"installer\"\npath = \"tools/installer.rs\""),
+               ["installer"]);
+    assert_eq!(bins("[package]\nname = \"pkg\"\nedition = \"2021\"\n[[bin]]\nname = \"renamed\"\npath = \"src/bin/extra.rs\""),
+               ["renamed", "pkg", "multi"]);
+    assert_eq!(bins("[package]\nname = \"pkg\"\n[[bin]]\nname = \"installer\"\npath = \"tools/installer.rs\""), ["installer"]);
+    assert_eq!(bins("[package]\nname = \"pkg\"\nedition.workspace = true"), ["pkg", "extra", "multi"]);
+}
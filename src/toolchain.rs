@@ -0,0 +1,382 @@
+use self::super::{ResourceCompiler, ResourceError, env_target_and_rc, env_for_target};
+use std::process::{Command, Stdio};
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::fmt::Write;
+use std::io;
+
+
+/// What kind of resource compiler a [`ToolchainCandidate`] is
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CompilerKind {
+    /// LLVM-RC (`llvm-rc`)
+    LlvmRc,
+    /// GNU windres or `llvm-windres`
+    WindRes,
+    /// Microsoft `RC.EXE`
+    RcExe,
+    /// `zig rc`
+    ZigRc,
+    /// The built-in compiler
+    Builtin,
+}
+
+impl Display for CompilerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.write_str(match self {
+            CompilerKind::LlvmRc => "llvm-rc",
+            CompilerKind::WindRes => "windres",
+            CompilerKind::RcExe => "rc.exe",
+            CompilerKind::ZigRc => "zig rc",
+            CompilerKind::Builtin => "built-in",
+        })
+    }
+}
+
+
+/// A resource compiler to try, cf. [`Resource::compilers()`](crate::Resource::compilers)
+///
+/// Not every host can drive every kind: `RC.EXE` can only be used on Windows MSVC hosts, and only it and `zig rc` are usable there.
+///
+/// Parses from `builtin` (or `embed-resource`), `llvm-rc`, `windres`, `rc` (or `rc.exe`), or `zig`,
+/// each but the first optionally followed by `:EXECUTABLE`, like in `$EMBED_RESOURCE_COMPILERS`.
+///
+/// The external ones hold the executable to run: a name, looked up in `$PATH`, or a path.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CompilerPreference {
+    /// `llvm-rc` (preprocessed with the C compiler first)
+    LlvmRc(Cow<'static, str>),
+    /// `windres`, from MinGW or LLVM (as `llvm-windres`)
+    WindRes(Cow<'static, str>),
+    /// `rc.exe`, from the Windows SDK
+    RcExe(Cow<'static, str>),
+    /// `zig` (this runs `zig rc`)
+    ZigRc(Cow<'static, str>),
+    /// The built-in compiler, which needs no executable
+    Builtin,
+}
+
+/// The compilers to try, and where that list came from
+type Preferences<'c> = (String, Cow<'c, [CompilerPreference]>);
+
+impl CompilerPreference {
+    pub fn kind(&self) -> CompilerKind {
+        match self {
+            CompilerPreference::LlvmRc(_) => CompilerKind::LlvmRc,
+            CompilerPreference::WindRes(_) => CompilerKind::WindRes,
+            CompilerPreference::RcExe(_) => CompilerKind::RcExe,
+            CompilerPreference::ZigRc(_) => CompilerKind::ZigRc,
+            CompilerPreference::Builtin => CompilerKind::Builtin,
+        }
+    }
+
+    pub fn executable(&self) -> &str {
+        match self {
+            CompilerPreference::LlvmRc(exe) |
+            CompilerPreference::WindRes(exe) |
+            CompilerPreference::RcExe(exe) |
+            CompilerPreference::ZigRc(exe) => exe,
+            CompilerPreference::Builtin => "embed-resource",
+        }
+    }
+
+    /// Parse a comma-separated list, ignoring empty entries
+    pub fn parse_list(list: &str) -> Result<Vec<CompilerPreference>, ResourceError> {
+        list.split(',').map(str::trim).filter(|p| !p.is_empty()).map(str::parse).collect()
+    }
+
+    /// `compilers` if not empty, or else `$EMBED_RESOURCE_COMPILERS_$TARGET`, `$EMBED_RESOURCE_COMPILERS_${TARGET//-/_}`,
+    /// or `$EMBED_RESOURCE_COMPILERS`, if set and not empty, and where they came from
+    pub(crate) fn resolve<'c>(compilers: &'c [CompilerPreference], target: &str)
+                              -> Result<Option<Preferences<'c>>, ResourceError> {
+        if !compilers.is_empty() {
+            return Ok(Some(("preference".to_string(), Cow::Borrowed(compilers))));
+        }
+        Ok(match env_for_target("EMBED_RESOURCE_COMPILERS", target) {
+            Some((var, list)) => {
+                let list = CompilerPreference::parse_list(list.to_str().ok_or_else(|| ResourceError::UnknownCompiler(PathBuf::from(&list)))?)?;
+                if list.is_empty() {
+                    None
+                } else {
+                    Some((format!("${}", var), Cow::Owned(list)))
+                }
+            }
+            None => None,
+        })
+    }
+}
+
+impl FromStr for CompilerPreference {
+    type Err = ResourceError;
+
+    fn from_str(s: &str) -> Result<CompilerPreference, ResourceError> {
+        let (kind, exe) = match s.split_once(':') {
+            Some((kind, exe)) => (kind, Some(exe)),
+            None => (s, None),
+        };
+        let or = |default: &'static str| exe.map(|e| Cow::Owned(e.to_string())).unwrap_or(Cow::Borrowed(default));
+        Ok(match kind {
+            "builtin" | "embed-resource" if exe.is_none() => CompilerPreference::Builtin,
+            "llvm-rc" => CompilerPreference::LlvmRc(or("llvm-rc")),
+            "windres" => CompilerPreference::WindRes(or("windres")),
+            "rc" | "rc.exe" => CompilerPreference::RcExe(or("rc.exe")),
+            "zig" => CompilerPreference::ZigRc(or("zig")),
+            _ => return Err(ResourceError::UnknownCompiler(PathBuf::from(s))),
+        })
+    }
+}
+
+
+/// A resource compiler that was considered, cf. [`ToolchainReport`]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ToolchainCandidate {
+    /// Why it was considered, like `$RC`, `default`, or `fallback`
+    pub source: String,
+    pub executable: PathBuf,
+    /// `None` if it couldn't be determined
+    pub kind: Option<CompilerKind>,
+    /// Whether it exists and runs
+    pub available: bool,
+    /// The version line of its `--version` output or its help, if any
+    pub version: Option<String>,
+    /// Notable features, like `no-preprocess` for LLVM-RC versions that can skip their own preprocessing
+    pub capabilities: Vec<String>,
+    /// Why it's unavailable or unusable, or why its version couldn't be found
+    pub error: Option<String>,
+}
+
+impl ToolchainCandidate {
+    pub(crate) fn new<S: Into<String>, E: Into<PathBuf>>(source: S, executable: E, kind: Option<CompilerKind>, available: bool) -> ToolchainCandidate {
+        ToolchainCandidate {
+            source: source.into(),
+            executable: executable.into(),
+            kind,
+            available,
+            version: None,
+            capabilities: vec![],
+            error: None,
+        }
+    }
+}
+
+
+/// Every resource compiler [`compile()`](crate::compile) would consider for `$TARGET`, in order, and which one it'd use
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+///
+/// fn main() {
+///     let report = embed_resource::ToolchainReport::probe();
+///     eprintln!("{}", report);
+///     std::fs::write("toolchain.json", report.to_json()).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ToolchainReport {
+    pub target: Option<String>,
+    pub candidates: Vec<ToolchainCandidate>,
+    /// Index into `candidates`
+    pub chosen: Option<usize>,
+    /// Why no resource will be compiled, if none would be: not building for Windows, or no usable compiler
+    pub error: Option<String>,
+}
+
+impl ToolchainReport {
+    /// Probe for resource compilers like [`compile()`](crate::compile) would, and also find out their versions.
+    pub fn probe() -> ToolchainReport {
+        ToolchainReport::probe_with(&[])
+    }
+
+    /// Probe for resource compilers like [`Resource::compilers(compilers)`](crate::Resource::compilers) would.
+    pub fn probe_with(compilers: &[CompilerPreference]) -> ToolchainReport {
+        let mut candidates = vec![];
+        let target = env_target_and_rc().map(|(target, _)| target);
+        let error = match target.as_ref().map(|target| ResourceCompiler::probe(target, compilers, &mut candidates).is_supported()) {
+            Ok(None) => None,
+            Ok(Some(None)) => Some("not building for Windows".to_string()),
+            Ok(Some(Some(err))) => Some(err.to_string()),
+            Err(err) => Some(err.to_string()),
+        };
+        // Discovery stops at the compiler it picks, so that's the last one it recorded
+        let chosen = if error.is_none() { candidates.len().checked_sub(1) } else { None };
+
+        // Not knowing the version doesn't make it any less usable
+        for cand in candidates.iter_mut().filter(|c| c.available && c.kind != Some(CompilerKind::Builtin)) {
+            match tool_version(&cand.executable, cand.kind == Some(CompilerKind::ZigRc)) {
+                Ok(version) => cand.version = version,
+                Err(err) => cand.error = Some(err.to_string()),
+            }
+        }
+        for cand in candidates.iter_mut().filter(|c| c.kind == Some(CompilerKind::Builtin)) {
+            cand.version = Some(env!("CARGO_PKG_VERSION").to_string());
+        }
+
+        ToolchainReport {
+            target: target.ok(),
+            chosen,
+            candidates,
+            error,
+        }
+    }
+
+    /// The chosen compiler, if any
+    pub fn chosen(&self) -> Option<&ToolchainCandidate> {
+        self.chosen.map(|i| &self.candidates[i])
+    }
+
+    /// Render as a JSON object with the same fields as this struct, `kind` being rendered like by `Display`.
+    pub fn to_json(&self) -> String {
+        let mut ret = String::from("{\n");
+        let _ = writeln!(ret, "  \"target\": {},", json_opt(self.target.as_deref()));
+        ret.push_str("  \"candidates\": [");
+        for (i, cand) in self.candidates.iter().enumerate() {
+            ret.push_str(if i == 0 { "\n" } else { ",\n" });
+            let _ = writeln!(ret, "    {{");
+            let _ = writeln!(ret, "      \"source\": {},", json_str(&cand.source));
+            let _ = writeln!(ret, "      \"executable\": {},", json_str(&cand.executable.to_string_lossy()));
+            let _ = writeln!(ret, "      \"kind\": {},", json_opt(cand.kind.map(|k| k.to_string()).as_deref()));
+            let _ = writeln!(ret, "      \"available\": {},", cand.available);
+            let _ = writeln!(ret, "      \"version\": {},", json_opt(cand.version.as_deref()));
+            let _ = writeln!(ret,
+                             "      \"capabilities\": [{}],",
+                             cand.capabilities.iter().map(|c| json_str(c)).collect::<Vec<_>>().join(", "));
+            let _ = writeln!(ret, "      \"error\": {}", json_opt(cand.error.as_deref()));
+            ret.push_str("    }");
+        }
+        if !self.candidates.is_empty() {
+            ret.push_str("\n  ");
+        }
+        ret.push_str("],\n");
+        let _ = writeln!(ret, "  \"chosen\": {},", self.chosen.map(|c| c.to_string()).unwrap_or_else(|| "null".to_string()));
+        let _ = writeln!(ret, "  \"error\": {}", json_opt(self.error.as_deref()));
+        ret.push_str("}\n");
+        ret
+    }
+}
+
+impl Display for ToolchainReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        writeln!(f, "target: {}", self.target.as_deref().unwrap_or("unknown"))?;
+        for (i, cand) in self.candidates.iter().enumerate() {
+            write!(f,
+                   "{} {}: {} ({})",
+                   if Some(i) == self.chosen { "*" } else { "-" },
+                   cand.source,
+                   cand.executable.display(),
+                   cand.kind.map(|k| k.to_string()).unwrap_or_else(|| "unknown kind".to_string()))?;
+            if let Some(version) = cand.version.as_ref() {
+                write!(f, ", {}", version)?;
+            }
+            for cap in &cand.capabilities {
+                write!(f, ", {}", cap)?;
+            }
+            if !cand.available {
+                f.write_str(", unavailable")?;
+            }
+            if let Some(error) = cand.error.as_ref() {
+                write!(f, ": {}", error)?;
+            }
+            writeln!(f)?;
+        }
+        match (self.chosen(), self.error.as_ref()) {
+            (_, Some(error)) => writeln!(f, "no resource compiler: {}", error),
+            (Some(chosen), None) => writeln!(f, "using: {}", chosen.executable.display()),
+            (None, None) => writeln!(f, "no resource compiler"),
+        }
+    }
+}
+
+
+/// Ask `exe` for its version, as windres and LLVM tools like (but LLVM-RC doesn't),
+/// then for help, which is where `RC.EXE` puts it; `zig` is asked with `zig version`
+fn tool_version(exe: &PathBuf, zig: bool) -> io::Result<Option<String>> {
+    let has_version = |l: &&str| l.to_ascii_lowercase().contains("version") && l.bytes().any(|b| b.is_ascii_digit());
+
+    let out = Command::new(exe).arg(if zig { "version" } else { "--version" }).stdin(Stdio::null()).output()?;
+    if out.status.success() {
+        let out = String::from_utf8_lossy(&out.stdout);
+        // "GNU windres (GNU Binutils) 2.41", but "llvm-windres, compatible with GNU windres\nDebian LLVM version 14.0.6"
+        let mut lines = out.lines().map(str::trim).filter(|l| !l.is_empty());
+        if let Some(first) = lines.next() {
+            let version = if first.bytes().any(|b| b.is_ascii_digit()) {
+                first
+            } else {
+                lines.find(has_version).unwrap_or(first)
+            };
+            return Ok(Some(version.to_string()));
+        }
+    }
+
+    let out = Command::new(exe).arg("/?").stdin(Stdio::null()).output()?;
+    Ok(String::from_utf8_lossy(&out.stdout).lines().find(has_version).map(|l| l.trim().to_string()))
+}
+
+fn json_str(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(ret, "\\u{:04x}", c as u32);
+            }
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn json_opt(s: Option<&str>) -> String {
+    s.map(json_str).unwrap_or_else(|| "null".to_string())
+}
+
+
+#[test]
+fn parse_preferences() {
+    assert_eq!(CompilerPreference::parse_list("llvm-rc:llvm-rc-18, windres:C:\\mingw\\bin\\windres.exe,,builtin").unwrap(),
+               vec![CompilerPreference::LlvmRc("llvm-rc-18".into()),
+                    CompilerPreference::WindRes("C:\\mingw\\bin\\windres.exe".into()),
+                    CompilerPreference::Builtin]);
+    assert_eq!(CompilerPreference::parse_list("rc, llvm-rc, zig:/opt/zig/zig").unwrap(),
+               vec![CompilerPreference::RcExe("rc.exe".into()), CompilerPreference::LlvmRc("llvm-rc".into()), CompilerPreference::ZigRc("/opt/zig/zig".into())]);
+    assert_eq!(CompilerPreference::parse_list("").unwrap(), vec![]);
+    assert_eq!(CompilerPreference::parse_list("llvm-rc,clang").unwrap_err().to_string(), "unknown RC compiler variant: clang");
+    assert!(CompilerPreference::parse_list("builtin:x").is_err());
+}
+
+#[test]
+fn render() {
+    let mut llvm_rc = ToolchainCandidate::new("default", "llvm-rc", Some(CompilerKind::LlvmRc), false);
+    llvm_rc.error = Some("missing compiler: tried llvm-rc".to_string());
+    let mut builtin = ToolchainCandidate::new("fallback", "embed-resource", Some(CompilerKind::Builtin), true);
+    builtin.version = Some("3.0.10".to_string());
+    builtin.capabilities.push("\"quoted\"\tcap".to_string());
+    let report = ToolchainReport {
+        target: Some("x86_64-pc-windows-msvc".to_string()),
+        candidates: vec![llvm_rc, builtin],
+        chosen: Some(1),
+        error: None,
+    };
+
+    assert_eq!(report.to_string(),
+               "target: x86_64-pc-windows-msvc\n\
+                - default: llvm-rc (llvm-rc), unavailable: missing compiler: tried llvm-rc\n\
+                * fallback: embed-resource (built-in), 3.0.10, \"quoted\"\tcap\n\
+                using: embed-resource\n");
+    assert_eq!(report.to_json(), include_str!("../test-data/toolchain-report.json"));
+
+    let report = ToolchainReport {
+        target: Some("x86_64-unknown-linux-gnu".to_string()),
+        candidates: vec![],
+        chosen: None,
+        error: Some("not building for Windows".to_string()),
+    };
+    assert_eq!(report.to_json(),
+               "{\n  \"target\": \"x86_64-unknown-linux-gnu\",\n  \"candidates\": [],\n  \"chosen\": null,\n  \"error\": \"not building for Windows\"\n}\n");
+}
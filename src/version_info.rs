@@ -0,0 +1,266 @@
+use self::super::{CompilationResult, Resource};
+use std::fmt::Write;
+use std::env;
+
+
+const VFT_APP: u32 = 0x00000001;
+const VFT_DLL: u32 = 0x00000002;
+
+
+/// A `VERSIONINFO` resource, cf. [`Resource::version_info()`].
+///
+/// All strings are written as `StringFileInfo` values in a single block for [`language()`](Self::language),
+/// which is also the only `VarFileInfo` `Translation`.
+///
+/// https://learn.microsoft.com/en-us/windows/win32/menurc/versioninfo-resource
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate embed_resource;
+/// use embed_resource::VersionInfo;
+///
+/// fn main() {
+///     VersionInfo::from_cargo_env()
+///         .string("LegalCopyright", "Copyright (c) 2016 nabijaczleweli")
+///         .compile()
+///         .manifest_optional()
+///         .unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VersionInfo {
+    file_version: [u16; 4],
+    product_version: [u16; 4],
+    file_flags: u32,
+    file_os: u32,
+    /// `VFT_APP`, or `VFT_DLL` for [`LinkTarget::Cdylib`](crate::LinkTarget::Cdylib), if unset
+    file_type: Option<u32>,
+    file_subtype: u32,
+    language: u16,
+    codepage: u16,
+    strings: Vec<(String, String)>,
+    /// `$CARGO_PKG_NAME`, if from [`from_cargo_env()`](Self::from_cargo_env), to make `OriginalFilename` a `.dll` for DLLs
+    cargo_name: Option<String>,
+}
+
+impl VersionInfo {
+    /// Version 0.0.0.0 of a Windows NT application (`VOS_NT_WINDOWS32`, `VFT_APP`), in US English (0x0409) Unicode (1200), with no strings.
+    ///
+    /// If the resource is only linked to [`LinkTarget::Cdylib`](crate::LinkTarget::Cdylib), it's a DLL (`VFT_DLL`) instead,
+    /// unless [`file_type()`](Self::file_type) says otherwise.
+    pub fn new() -> VersionInfo {
+        VersionInfo {
+            file_version: [0; 4],
+            product_version: [0; 4],
+            file_flags: 0,
+            file_os: 0x00040004,
+            file_type: None,
+            file_subtype: 0,
+            language: 0x0409,
+            codepage: 1200,
+            strings: vec![],
+            cargo_name: None,
+        }
+    }
+
+    /// Fill in a new version from the `CARGO_PKG_*` variables Cargo sets for build scripts.
+    ///
+    ///   * `FILEVERSION` and `PRODUCTVERSION` are `$CARGO_PKG_VERSION_MAJOR.$CARGO_PKG_VERSION_MINOR.$CARGO_PKG_VERSION_PATCH.0`,
+    ///   * `FileVersion` and `ProductVersion` are `$CARGO_PKG_VERSION`,
+    ///   * `ProductName` and `InternalName` are `$CARGO_PKG_NAME`, and `OriginalFilename` is `$CARGO_PKG_NAME.exe`
+    ///     (or, if it's [a DLL](Self::new), `${CARGO_PKG_NAME//-/_}.dll`, like Cargo names `cdylib`s),
+    ///   * `FileDescription` is `$CARGO_PKG_DESCRIPTION`, or `$CARGO_PKG_NAME` if there's no description,
+    ///   * `CompanyName` is the names (without e-mail addresses) from `$CARGO_PKG_AUTHORS`, if any.
+    ///
+    /// Everything can be overriden afterwards: binaries not named after the package (like `[[bin]]`s with a different `name`)
+    /// need their own `OriginalFilename`.
+    pub fn from_cargo_env() -> VersionInfo {
+        let var = |name| env::var(name).unwrap_or_default();
+        let version_part = |name| var(name).parse().unwrap_or(0);
+        let version = [version_part("CARGO_PKG_VERSION_MAJOR"), version_part("CARGO_PKG_VERSION_MINOR"), version_part("CARGO_PKG_VERSION_PATCH"), 0];
+        let name = var("CARGO_PKG_NAME");
+        let description = var("CARGO_PKG_DESCRIPTION");
+        let authors = var("CARGO_PKG_AUTHORS").split(':').map(|a| a.split(" <").next().unwrap().trim()).filter(|a| !a.is_empty()).collect::<Vec<_>>().join(", ");
+
+        let mut ret = VersionInfo::new();
+        ret.file_version(version).product_version(version);
+        if !authors.is_empty() {
+            ret.string("CompanyName", &authors);
+        }
+        ret.string("FileDescription", if description.is_empty() { &name } else { &description })
+            .string("FileVersion", &var("CARGO_PKG_VERSION"))
+            .string("InternalName", &name)
+            .string("OriginalFilename", &format!("{}.exe", name))
+            .string("ProductName", &name)
+            .string("ProductVersion", &var("CARGO_PKG_VERSION"));
+        ret.cargo_name = Some(name);
+        ret
+    }
+
+    /// Set `FILEVERSION`.
+    pub fn file_version(&mut self, version: [u16; 4]) -> &mut VersionInfo {
+        self.file_version = version;
+        self
+    }
+
+    /// Set `PRODUCTVERSION`.
+    pub fn product_version(&mut self, version: [u16; 4]) -> &mut VersionInfo {
+        self.product_version = version;
+        self
+    }
+
+    /// Set `FILEFLAGS` (`VS_FF_*`); `FILEFLAGSMASK` is always `VS_FFI_FILEFLAGSMASK`.
+    pub fn file_flags(&mut self, flags: u32) -> &mut VersionInfo {
+        self.file_flags = flags;
+        self
+    }
+
+    /// Set `FILEOS` (`VOS_*`).
+    pub fn file_os(&mut self, os: u32) -> &mut VersionInfo {
+        self.file_os = os;
+        self
+    }
+
+    /// Set `FILETYPE` (`VFT_*`), e.g. `VFT_DLL` (2) for libraries.
+    pub fn file_type(&mut self, tp: u32) -> &mut VersionInfo {
+        self.file_type = Some(tp);
+        self
+    }
+
+    /// Set `FILESUBTYPE` (`VFT2_*`).
+    pub fn file_subtype(&mut self, subtype: u32) -> &mut VersionInfo {
+        self.file_subtype = subtype;
+        self
+    }
+
+    /// Set the language ID and codepage of the strings.
+    pub fn language(&mut self, language: u16, codepage: u16) -> &mut VersionInfo {
+        self.language = language;
+        self.codepage = codepage;
+        self
+    }
+
+    /// Set a `StringFileInfo` value, replacing the previous one with the same key, if any.
+    pub fn string(&mut self, key: &str, value: &str) -> &mut VersionInfo {
+        match self.strings.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.strings.push((key.to_string(), value.to_string())),
+        }
+        self
+    }
+
+    /// Remove a `StringFileInfo` value.
+    pub fn remove_string(&mut self, key: &str) -> &mut VersionInfo {
+        self.strings.retain(|(k, _)| k != key);
+        self
+    }
+
+    /// Compile just this version info and link it to all binaries, like [`compile()`](crate::compile).
+    ///
+    /// Use [`Resource::version_info()`] to add it to a resource file or link it elsewhere.
+    pub fn compile(&self) -> CompilationResult {
+        Resource::generated().version_info(self).compile()
+    }
+
+    /// This, as a DLL's unless [`file_type()`](Self::file_type) says otherwise
+    pub(crate) fn dll_by_default(&self) -> VersionInfo {
+        let mut ret = VersionInfo {
+            file_type: Some(self.file_type.unwrap_or(VFT_DLL)),
+            ..self.clone()
+        };
+        if let (None, Some(name)) = (self.file_type, self.cargo_name.as_ref()) {
+            let exe = format!("{}.exe", name);
+            if ret.strings.iter().any(|(k, v)| k == "OriginalFilename" && *v == exe) {
+                ret.string("OriginalFilename", &format!("{}.dll", name.replace('-', "_")));
+            }
+        }
+        ret
+    }
+
+    /// Render as a resource script statement
+    pub(crate) fn to_rc(&self) -> String {
+        let mut ret = String::new();
+        let _ = writeln!(ret, "1 VERSIONINFO");
+        let _ = writeln!(ret, "FILEVERSION {},{},{},{}", self.file_version[0], self.file_version[1], self.file_version[2], self.file_version[3]);
+        let _ = writeln!(ret,
+                         "PRODUCTVERSION {},{},{},{}",
+                         self.product_version[0],
+                         self.product_version[1],
+                         self.product_version[2],
+                         self.product_version[3]);
+        let _ = writeln!(ret, "FILEFLAGSMASK 0x3F");
+        let _ = writeln!(ret, "FILEFLAGS {:#X}", self.file_flags);
+        let _ = writeln!(ret, "FILEOS {:#X}", self.file_os);
+        let _ = writeln!(ret, "FILETYPE {:#X}", self.file_type.unwrap_or(VFT_APP));
+        let _ = writeln!(ret, "FILESUBTYPE {:#X}", self.file_subtype);
+        let _ = writeln!(ret, "BEGIN");
+        let _ = writeln!(ret, "    BLOCK \"StringFileInfo\"");
+        let _ = writeln!(ret, "    BEGIN");
+        let _ = writeln!(ret, "        BLOCK \"{:04X}{:04X}\"", self.language, self.codepage);
+        let _ = writeln!(ret, "        BEGIN");
+        for (k, v) in &self.strings {
+            let _ = writeln!(ret, "            VALUE {}, {}", rc_string(k, false), rc_string(v, true));
+        }
+        let _ = writeln!(ret, "        END");
+        let _ = writeln!(ret, "    END");
+        let _ = writeln!(ret, "    BLOCK \"VarFileInfo\"");
+        let _ = writeln!(ret, "    BEGIN");
+        let _ = writeln!(ret, "        VALUE \"Translation\", {:#06X}, {}", self.language, self.codepage);
+        let _ = writeln!(ret, "    END");
+        let _ = writeln!(ret, "END");
+        ret
+    }
+}
+
+impl Default for VersionInfo {
+    fn default() -> VersionInfo {
+        VersionInfo::new()
+    }
+}
+
+
+/// A wide string literal; everything but printable ASCII is escaped, so the codepage doesn't matter
+pub(crate) fn rc_string(s: &str, nul: bool) -> String {
+    let mut ret = String::from("L\"");
+    for c in s.encode_utf16() {
+        match c {
+            0x22 => ret.push_str("\"\""),
+            0x5C => ret.push_str("\\\\"),
+            0x20..=0x7E => ret.push(c as u8 as char),
+            _ => {
+                let _ = write!(ret, "\\x{:04X}", c);
+            }
+        }
+    }
+    if nul {
+        ret.push_str("\\0");
+    }
+    ret.push('"');
+    ret
+}
+
+
+#[test]
+fn to_rc() {
+    assert_eq!(VersionInfo::new()
+                   .file_version([3, 0, 10, 0])
+                   .product_version([3, 0, 10, 1])
+                   .file_type(2)
+                   .language(0x0809, 1200)
+                   .string("CompanyName", "nabijaczleweli")
+                   .string("ProductName", "\"rust\\embed\" – resource")
+                   .string("CompanyName", "Łukasz")
+                   .to_rc(),
+               include_str!("../test-data/version-info.rc"));
+}
+
+#[test]
+fn dll_original_filename() {
+    let original = |vi: &VersionInfo| vi.strings.iter().find(|(k, _)| k == "OriginalFilename").unwrap().1.clone();
+    let mut vi = VersionInfo::new();
+    vi.string("OriginalFilename", "my-lib.exe").cargo_name = Some("my-lib".to_string());
+    assert_eq!(original(&vi.dll_by_default()), "my_lib.dll");
+    assert_eq!(original(&vi.clone().file_type(1).dll_by_default()), "my-lib.exe");
+    assert_eq!(original(&vi.string("OriginalFilename", "renamed.dll").dll_by_default()), "renamed.dll");
+}
@@ -1,53 +1,146 @@
-use self::super::{ParameterBundle, env_target_and_rc, apply_parameters};
-use std::path::{PathBuf, Path, MAIN_SEPARATOR};
+use self::super::{CompilerPreference, ToolchainCandidate, ParameterBundle, ResourceError, CompilerKind, apply_parameters, env_for_target,
+                  compile_builtin, is_zig_rc, error};
+use std::path::{PathBuf, MAIN_SEPARATOR};
 use std::sync::atomic::Ordering::SeqCst;
 use std::sync::atomic::AtomicBool;
-use std::process::Command;
+use std::process::{Command, Stdio};
 use vswhom::VsFindResult;
-use std::borrow::Cow;
 use winreg::enums::*;
 use std::ffi::OsStr;
 use std::{env, fs};
 use winreg;
 
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
-pub struct ResourceCompiler;
+/// What makes `zig` `RC.EXE`, reading sources as UTF-8
+const ZIG_RC_ARGS: &[&str] = &["rc", "/c65001"];
+
+#[derive(Debug, Clone)]
+enum Rc {
+    /// `RC.EXE`, or `zig` and the arguments that make it one
+    Exe(PathBuf, &'static [&'static str]),
+    /// The built-in compiler, `fallback` if only because no other was found
+    Builtin { fallback: bool, },
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceCompiler {
+    rc: Result<Rc, Option<ResourceError>>,
+    target: String,
+}
 
 
 impl ResourceCompiler {
     #[inline(always)]
-    pub fn new() -> ResourceCompiler {
-        ResourceCompiler
+    pub fn new(target: &str, compilers: &[CompilerPreference]) -> ResourceCompiler {
+        ResourceCompiler::probe(target, compilers, &mut vec![])
+    }
+
+    /// Like `new()`, recording every compiler considered in `candidates`
+    pub fn probe(target: &str, compilers: &[CompilerPreference], candidates: &mut Vec<ToolchainCandidate>) -> ResourceCompiler {
+        ResourceCompiler {
+            rc: probe(target, compilers, candidates).map_err(Some),
+            target: target.to_string(),
+        }
     }
 
     #[inline(always)]
-    pub fn is_supported(&mut self) -> Option<Cow<'static, str>> {
-        None
+    pub fn is_supported(&mut self) -> Option<Option<ResourceError>> {
+        self.rc.as_mut().err().map(Option::take)
+    }
+
+    /// The compiler `compile_resource()` will use
+    pub fn chosen(&self) -> Option<(CompilerKind, &OsStr)> {
+        self.rc.as_ref().ok().map(|rc| match rc {
+            Rc::Exe(rc, args) => (if args.is_empty() { CompilerKind::RcExe } else { CompilerKind::ZigRc }, rc.as_os_str()),
+            Rc::Builtin { .. } => (CompilerKind::Builtin, OsStr::new("embed-resource")),
+        })
+    }
+
+    /// Whether that's the built-in compiler, only because no other was found
+    pub fn is_fallback(&self) -> bool {
+        matches!(self.rc, Ok(Rc::Builtin { fallback: true }))
     }
 
     pub fn compile_resource<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(
         self, out_dir: &str, prefix: &str, resource: &str, parameters: ParameterBundle<Ms, Mi, Is, Ii>)
-        -> Result<String, Cow<'static, str>> {
-        let out_file = format!("{}{}{}.lib", out_dir, MAIN_SEPARATOR, prefix);
-        let (_, rc) = env_target_and_rc()?;
-        let rc = rc.map(PathBuf::from).or_else(|| find_windows_sdk_tool_impl("rc.exe"));
-        // `.res`es are linkable under MSVC as well as normal libraries.
-        if !apply_parameters(Command::new(rc.as_deref().unwrap_or(Path::new("rc.exe"))).args(&["/fo", &out_file, "/I", out_dir]),
-                             "/D",
-                             "/I",
-                             parameters)
-            .arg(resource)
-            .status()
-            .map_err(|_| Cow::from("Are you sure you have RC.EXE in your $PATH or ${RC_$TARGET} or $RC is set?"))?
-            .success() {
-            return Err("RC.EXE failed to compile specified resource file".into());
+        -> Result<String, ResourceError> {
+        let out_file = format!("{}{}{}.res", out_dir, MAIN_SEPARATOR, prefix);
+        // `.res`es are linkable under MSVC as well as objects.
+        match self.rc.expect("Not supported but we got to compile_resource()?") {
+            Rc::Exe(rc, rc_args) => {
+                error::run(apply_parameters(Command::new(&rc).args(rc_args).args(["/fo", &out_file, "/I", out_dir]), "/D", "/I", parameters).arg(resource),
+                           &rc,
+                           None)?;
+            }
+            Rc::Builtin { .. } => compile_builtin(&self.target, out_dir, prefix, &out_file, resource, parameters)?,
         }
         Ok(out_file)
     }
 }
 
 
+fn probe(target: &str, compilers: &[CompilerPreference], candidates: &mut Vec<ToolchainCandidate>)
+         -> Result<Rc, ResourceError> {
+    if let Some((source, compilers)) = CompilerPreference::resolve(compilers, target)? {
+        let mut errors = vec![];
+        for pref in compilers.iter() {
+            let exe = PathBuf::from(pref.executable());
+            let args: &'static [&'static str] = match pref {
+                CompilerPreference::ZigRc(_) => ZIG_RC_ARGS,
+                _ => &[],
+            };
+            let err = match pref {
+                CompilerPreference::RcExe(_) |
+                CompilerPreference::ZigRc(_) if Command::new(&exe).arg("/?").stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok() => {
+                    candidates.push(ToolchainCandidate::new(&*source, &exe, Some(pref.kind()), true));
+                    return Ok(Rc::Exe(exe, args));
+                }
+                CompilerPreference::Builtin => {
+                    candidates.push(ToolchainCandidate::new(&*source, &exe, Some(pref.kind()), true));
+                    return Ok(Rc::Builtin { fallback: false });
+                }
+                CompilerPreference::RcExe(_) |
+                CompilerPreference::ZigRc(_) => ResourceError::CompilerNotFound { tried: vec![exe.clone()] },
+                pref => {
+                    ResourceError::UnsupportedCompiler {
+                        exe: exe.clone(),
+                        kind: pref.kind(),
+                    }
+                }
+            };
+            let mut cand = ToolchainCandidate::new(&*source, exe, Some(pref.kind()), false);
+            cand.error = Some(err.to_string());
+            candidates.push(cand);
+            errors.push(err);
+        }
+        return Err(ResourceError::NoUsableCompiler(errors));
+    }
+
+    if let Some((var, rc)) = env_for_target("RC", target) {
+        if rc == "embed-resource" {
+            candidates.push(ToolchainCandidate::new(format!("${}", var), &rc, Some(CompilerKind::Builtin), true));
+            return Ok(Rc::Builtin { fallback: false });
+        }
+        if is_zig_rc(&rc).unwrap_or(false) {
+            candidates.push(ToolchainCandidate::new(format!("${}", var), &rc, Some(CompilerKind::ZigRc), true));
+            return Ok(Rc::Exe(PathBuf::from(rc), ZIG_RC_ARGS));
+        }
+        candidates.push(ToolchainCandidate::new(format!("${}", var), &rc, Some(CompilerKind::RcExe), true));
+        return Ok(Rc::Exe(PathBuf::from(rc), &[]));
+    }
+    if let Some(rc) = find_windows_sdk_tool_candidates("rc.exe", candidates) {
+        return Ok(Rc::Exe(rc, &[]));
+    }
+    let on_path = Command::new("rc.exe").arg("/?").stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok();
+    candidates.push(ToolchainCandidate::new("$PATH", "rc.exe", Some(CompilerKind::RcExe), on_path));
+    if on_path {
+        return Ok(Rc::Exe(PathBuf::from("rc.exe"), &[]));
+    }
+    candidates.push(ToolchainCandidate::new("fallback", "embed-resource", Some(CompilerKind::Builtin), true));
+    Ok(Rc::Builtin { fallback: true })
+}
+
+
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 enum Arch {
     X86,
@@ -56,18 +149,31 @@ enum Arch {
 }
 
 pub fn find_windows_sdk_tool_impl(tool: &str) -> Option<PathBuf> {
+    find_windows_sdk_tool_candidates(tool, &mut vec![])
+}
+
+/// `find_windows_sdk_tool_impl()`, recording every place it looked in `candidates`
+fn find_windows_sdk_tool_candidates(tool: &str, candidates: &mut Vec<ToolchainCandidate>) -> Option<PathBuf> {
     let arch = match env::var_os("HOST").expect("No HOST env var").as_encoded_bytes() {
         [b'x', b'8', b'6', b'_', b'6', b'4', ..] => Arch::X64, // "x86_64"
         [b'a', b'a', b'r', b'c', b'h', b'6', b'4', ..] => Arch::AArch64, // "aarch64"
         _ => Arch::X86,
     };
 
-    find_windows_10_kits_tool("KitsRoot10", arch, tool)
-        .or_else(|| find_windows_kits_tool("KitsRoot10", arch, tool))
-        .or_else(|| find_windows_kits_tool("KitsRoot81", arch, tool))
-        .or_else(|| find_windows_kits_tool("KitsRoot", arch, tool))
-        .or_else(|| find_latest_windows_sdk_tool(arch, tool))
-        .or_else(|| find_with_vswhom(arch, tool))
+    let searches: [(&str, &dyn Fn() -> Option<PathBuf>); 6] = [("Windows 10 Kits (KitsRoot10, versioned)", &|| find_windows_10_kits_tool("KitsRoot10", arch, tool)),
+                                                                ("Windows Kits (KitsRoot10)", &|| find_windows_kits_tool("KitsRoot10", arch, tool)),
+                                                                ("Windows Kits (KitsRoot81)", &|| find_windows_kits_tool("KitsRoot81", arch, tool)),
+                                                                ("Windows Kits (KitsRoot)", &|| find_windows_kits_tool("KitsRoot", arch, tool)),
+                                                                ("Windows SDK (CurrentInstallFolder)", &|| find_latest_windows_sdk_tool(arch, tool)),
+                                                                ("Visual Studio (vswhom)", &|| find_with_vswhom(arch, tool))];
+    for (source, search) in searches {
+        let found = search();
+        candidates.push(ToolchainCandidate::new(source, found.clone().unwrap_or_else(|| PathBuf::from(tool)), Some(CompilerKind::RcExe), found.is_some()));
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
 }
 
 
@@ -1,30 +1,36 @@
-use self::super::ParameterBundle;
+use self::super::{CompilerPreference, ToolchainCandidate, ParameterBundle, ResourceError, CompilerKind};
 use self::super::windres::*;
 use std::path::{PathBuf, MAIN_SEPARATOR};
 use std::borrow::Cow;
 use std::ffi::OsStr;
-use std::{env, mem};
+use std::{env, iter};
 
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub struct ResourceCompiler {
+    target: String,
     windres_target: Cow<'static, OsStr>,
-    compiler: Result<Compiler, Cow<'static, str>>,
+    compiler: Result<Compiler, Option<ResourceError>>,
 }
 
 
 impl ResourceCompiler {
     #[inline(always)]
-    pub fn new() -> ResourceCompiler {
+    pub fn new(target: &str, compilers: &[CompilerPreference]) -> ResourceCompiler {
+        ResourceCompiler::probe(target, compilers, &mut vec![])
+    }
+
+    /// Like `new()`, recording every compiler considered in `candidates`
+    pub fn probe(target: &str, compilers: &[CompilerPreference], candidates: &mut Vec<ToolchainCandidate>) -> ResourceCompiler {
         // Under some msys2 environments, $MINGW_CHOST has the correct target for
         // GNU windres or llvm-windres (clang32, clang64, or clangarm64)
-        let target = env::var_os("TARGET").expect("No TARGET env var");
-        let compiler = Compiler::choose(&target);
+        let compiler = Compiler::choose(target, compilers, candidates);
         ResourceCompiler {
+            target: target.to_string(),
             windres_target: match compiler.as_ref().map(|c| c.tp) {
                 Ok(CompilerType::WindRes) => {
                     env::var_os("MINGW_CHOST").map(Cow::Owned).unwrap_or_else(|| {
-                        OsStr::new(match target.as_encoded_bytes() {
+                        OsStr::new(match target.as_bytes() {
                                 [b'x', b'8', b'6', b'_', b'6', b'4', ..] => "pe-x86-64", // "x86_64"
                                 [b'a', b'a', b'r', b'c', b'h', b'6', b'4', ..] => "pe-aarch64-little", // "aarch64"
                                 // windres has "pe-aarch64-little" in the strings but doesn't actually accept it on my machine,
@@ -36,22 +42,33 @@ impl ResourceCompiler {
                 }
                 _ => OsStr::new("").into(),
             },
-            compiler: compiler,
+            compiler,
         }
     }
 
     #[inline(always)]
-    pub fn is_supported(&mut self) -> Option<Cow<'static, str>> {
-        self.compiler.as_mut().err().map(|e| mem::replace(e, "".into()))
+    pub fn is_supported(&mut self) -> Option<Option<ResourceError>> {
+        self.compiler.as_mut().err().map(Option::take)
+    }
+
+    /// The compiler `compile_resource()` will use
+    pub fn chosen(&self) -> Option<(CompilerKind, &OsStr)> {
+        self.compiler.as_ref().ok().map(|c| (c.tp.kind(), &*c.executable))
+    }
+
+    /// Whether that's the built-in compiler, only because no other was found
+    pub fn is_fallback(&self) -> bool {
+        matches!(self.compiler, Ok(Compiler { tp: CompilerType::Builtin { fallback: true }, .. }))
     }
 
     pub fn compile_resource<Ms: AsRef<OsStr>, Mi: IntoIterator<Item = Ms>, Is: AsRef<OsStr>, Ii: IntoIterator<Item = Is>>(
         self, out_dir: &str, prefix: &str, resource: &str, parameters: ParameterBundle<Ms, Mi, Is, Ii>)
-        -> Result<String, Cow<'static, str>> {
+        -> Result<String, ResourceError> {
         let compiler = self.compiler.expect("Not supported but we got to compile_resource()?");
-        compiler.compile(out_dir,
+        compiler.compile(&self.target,
+                         out_dir,
                          prefix,
-                         format!("{}{}lib{}.a", out_dir, MAIN_SEPARATOR, prefix),
+                         format!("{}{}{}.o", out_dir, MAIN_SEPARATOR, prefix),
                          resource,
                          parameters,
                          "-fo",
@@ -60,20 +77,36 @@ impl ResourceCompiler {
                          |c| {
                              c.arg("--target")
                                  .arg(self.windres_target)
-                                 .args(&["-c", "65001"]) // UTF-8, cf. https://github.com/nabijaczleweli/rust-embed-resource/pull/73
+                                 .args(["-c", "65001"]) // UTF-8, cf. https://github.com/nabijaczleweli/rust-embed-resource/pull/73
                          })
     }
 }
 
 impl Compiler {
-    fn choose(target: &OsStr) -> Result<Compiler, Cow<'static, str>> {
-        match target.as_encoded_bytes() {
-            // "aarch64".."gnullvm"
-            // https://github.com/llvm/llvm-project/issues/125371
-            [b'a', b'a', b'r', b'c', b'h', b'6', b'4', .., b'g', b'n', b'u', b'l', b'l', b'v', b'm'] => Compiler::llvm_rc("llvm-rc".into()),
-
-            _ => Compiler::windres("windres".into()),
+    fn choose(target: &str, compilers: &[CompilerPreference], candidates: &mut Vec<ToolchainCandidate>) -> Result<Compiler, Option<ResourceError>> {
+        if let Some((source, compilers)) = CompilerPreference::resolve(compilers, target)? {
+            return Compiler::prefer(&compilers, &source, candidates).map_err(Some);
+        }
+        if let Some(compiler) = Compiler::from_env(target, candidates) {
+            return compiler.map_err(Some);
         }
+        match target.as_bytes() {
+                // "aarch64".."gnullvm"
+                // https://github.com/llvm/llvm-project/issues/125371
+                [b'a', b'a', b'r', b'c', b'h', b'6', b'4', .., b'g', b'n', b'u', b'l', b'l', b'v', b'm'] => {
+                    Compiler::discover(candidates, CompilerKind::LlvmRc, llvm_tool("llvm-rc"))
+                }
+
+                target => {
+                    let arch = String::from_utf8_lossy(target.split(|&b| b == b'-').next().unwrap_or_default());
+                    let prefixed = ("$PATH, prefixed", format!("{}-w64-mingw32-windres", arch));
+                    Compiler::discover(candidates,
+                                       CompilerKind::WindRes,
+                                       iter::once(default_tool("windres".to_string())).chain(iter::once(prefixed)).chain(llvm_tool("llvm-windres")))
+                }
+            }
+            .or_else(|_| Compiler::consider(candidates, "fallback", OsStr::new("embed-resource"), Some(CompilerKind::Builtin), Ok(Compiler::builtin(true))))
+            .map_err(Some)
     }
 }
 